@@ -0,0 +1,113 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use eui48::MacAddress;
+
+use crate::{
+    DeviceConfig, Error, ExpireSet, MapOptions, Network, NetworkConfig, PortConfig, Source,
+};
+
+/// How long hardware seeded without an expiry stays visible.
+const SEEDED_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Builds a network in memory, seeding what each port can see directly
+/// instead of polling for it.
+#[derive(Default)]
+pub struct NetworkBuilder {
+    config: NetworkConfig,
+    root: Option<PathBuf>,
+    visible: Vec<(String, String, MacAddress, Option<Instant>)>,
+}
+
+impl NetworkBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where any pollers find their files, defaults to the current directory.
+    pub fn root(mut self, root: PathBuf) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    pub fn options(mut self, options: MapOptions) -> Self {
+        self.config.options = options;
+        self
+    }
+
+    pub fn device(mut self, id: &str, mac: &[MacAddress], ports: &[&str]) -> Self {
+        self.config.devices.push(DeviceConfig {
+            id: id.to_owned(),
+            name: None,
+            device_type: Default::default(),
+            ip: None,
+            mac: mac.to_vec(),
+            ports: ports
+                .iter()
+                .map(|port| PortConfig {
+                    id: (*port).to_owned(),
+                    name: None,
+                    pollers: Vec::new(),
+                    bond: None,
+                    vlans: Vec::new(),
+                    ssid: None,
+                })
+                .collect(),
+            pollers: Vec::new(),
+            case_insensitive_ports: false,
+            strip_vlan_suffix: false,
+            auto_ports: false,
+            enabled: None,
+            root: None,
+        });
+        self
+    }
+
+    /// Makes hardware visible on a device's port for a day.
+    pub fn visible(mut self, device: &str, port: &str, macs: &[MacAddress]) -> Self {
+        for mac in macs {
+            self.visible
+                .push((device.to_owned(), port.to_owned(), *mac, None));
+        }
+        self
+    }
+
+    /// Makes hardware visible on a device's port until it expires.
+    pub fn visible_until(
+        mut self,
+        device: &str,
+        port: &str,
+        mac: MacAddress,
+        expiry: Instant,
+    ) -> Self {
+        self.visible
+            .push((device.to_owned(), port.to_owned(), mac, Some(expiry)));
+        self
+    }
+
+    pub fn build(self) -> Result<Network, Error> {
+        self.config.validate()?;
+        let root = self.root.unwrap_or_else(|| PathBuf::from("."));
+        let mut network = Network::new(self.config, &root);
+
+        let now = Instant::now();
+        for (device_id, port_id, mac, expiry) in self.visible {
+            let device = network
+                .devices
+                .values_mut()
+                .find(|d| d.id == device_id)
+                .ok_or_else(|| Error::UnknownDevice(device_id.clone()))?;
+            let port = device.ports.get_mut(&port_id).ok_or_else(|| {
+                Error::ConfigError(format!("{} has no port {}", device_id, port_id))
+            })?;
+
+            let mut visible = ExpireSet::default();
+            visible.insert(mac, expiry.unwrap_or(now + SEEDED_EXPIRY));
+            port.observe(visible, &Source::ForwardDb);
+        }
+
+        Ok(network)
+    }
+}
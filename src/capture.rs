@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use eui48::MacAddress;
+use pcap::Capture;
+
+use crate::error::Error;
+
+fn capture_error(error: pcap::Error) -> Error {
+    Error::CaptureError(error.to_string())
+}
+
+/// Extracts the source hardware address from an Ethernet frame.
+pub fn source_mac(frame: &[u8]) -> Option<MacAddress> {
+    let bytes: [u8; 6] = frame.get(6..12)?.try_into().ok()?;
+    Some(MacAddress::new(bytes))
+}
+
+/// Collects the source hardware of every frame read from the capture until
+/// it runs out of packets or the deadline passes.
+pub fn source_macs<T>(
+    capture: &mut Capture<T>,
+    deadline: Option<Instant>,
+) -> Result<Vec<MacAddress>, Error> {
+    let mut macs = Vec::new();
+
+    while deadline.map(|d| Instant::now() < d).unwrap_or(true) {
+        match capture.next_packet() {
+            Ok(packet) => macs.extend(source_mac(packet.data)),
+            Err(pcap::Error::TimeoutExpired) => continue,
+            Err(pcap::Error::NoMorePackets) => break,
+            Err(error) => return Err(capture_error(error)),
+        }
+    }
+
+    Ok(macs)
+}
+
+/// Sniffs traffic on an interface for a while and returns the hardware that
+/// sent it.
+pub fn listen(interface: &str, duration: Duration) -> Result<Vec<MacAddress>, Error> {
+    let mut capture = Capture::from_device(interface)
+        .map_err(capture_error)?
+        .promisc(true)
+        .timeout(100)
+        .open()
+        .map_err(capture_error)?;
+
+    source_macs(&mut capture, Some(Instant::now() + duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// Writes the frames out as a pcap file of Ethernet traffic.
+    fn pcap_file(name: &str, frames: &[&[u8]]) -> PathBuf {
+        let mut data = Vec::new();
+        for field in [0xa1b2c3d4u32, 0x00040002, 0, 0, 65535, 1] {
+            data.extend(field.to_le_bytes());
+        }
+        for frame in frames {
+            for field in [0u32, 0, frame.len() as u32, frame.len() as u32] {
+                data.extend(field.to_le_bytes());
+            }
+            data.extend(*frame);
+        }
+
+        let path = std::env::temp_dir().join(format!("netmap-{}-{}.pcap", name, process::id()));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn frames_are_read_from_a_capture_file() {
+        let frame = |src: [u8; 6]| {
+            let mut frame = vec![0xff; 6];
+            frame.extend(src);
+            frame.extend([0x08, 0x06, 0x00, 0x01]);
+            frame
+        };
+        let first = frame([0x00, 0x11, 0x22, 0x33, 0x44, 0x0a]);
+        let second = frame([0x00, 0x11, 0x22, 0x33, 0x44, 0x0b]);
+        let path = pcap_file("capture_file", &[&first, &[0xff; 8], &second]);
+
+        let mut capture = Capture::from_file(&path).unwrap();
+        let macs = source_macs(&mut capture, None).unwrap();
+        assert_eq!(
+            macs,
+            vec![
+                MacAddress::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x0a]),
+                MacAddress::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x0b]),
+            ]
+        );
+    }
+}
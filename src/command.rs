@@ -0,0 +1,60 @@
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use crate::error::Error;
+
+/// Runs a local command from the network's root directory and returns
+/// whatever it wrote to stdout, killing it if it runs past the timeout.
+pub fn run(
+    command: &str,
+    args: &[String],
+    root: &Path,
+    timeout: Duration,
+) -> Result<String, Error> {
+    let mut child = Command::new(command)
+        .args(args)
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::CommandError(format!("`{}` could not be run: {}", command, e)))?;
+
+    // Read as the command runs so it can't stall on a full pipe.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait().map_err(Error::IoError)? {
+            Some(status) => break status,
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::Timeout(format!("running `{}`", command)));
+            }
+            None => sleep(Duration::from_millis(10)),
+        }
+    };
+
+    if !status.success() {
+        return Err(Error::CommandError(format!(
+            "`{}` exited with {}",
+            command, status
+        )));
+    }
+
+    let output = reader
+        .join()
+        .map_err(|_| Error::CommandError(format!("`{}` output could not be read", command)))?
+        .map_err(Error::IoError)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
@@ -0,0 +1,27 @@
+use std::{net::IpAddr, sync::mpsc, thread, time::Duration};
+
+/// How long to wait for a reverse lookup before giving up on it.
+const LOOKUP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Finds the hostname of an address. The system resolver can't be given a
+/// timeout so lookups that take too long are left to finish in the background
+/// and treated as failed.
+pub fn reverse_lookup(address: IpAddr) -> Option<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(dns_lookup::lookup_addr(&address));
+    });
+
+    match receiver.recv_timeout(LOOKUP_TIMEOUT) {
+        Ok(Ok(name)) if name != address.to_string() => Some(name),
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => {
+            log::trace!("no hostname for {}: {}", address, e);
+            None
+        }
+        Err(_) => {
+            log::trace!("lookup of {} timed out", address);
+            None
+        }
+    }
+}
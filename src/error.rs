@@ -8,4 +8,38 @@ pub enum Error {
     IoError(io::Error),
     #[error("Parse Error `{0}`")]
     ParseError(serde_json::Error),
+    #[error("YAML Error `{0}`")]
+    YamlError(serde_yaml::Error),
+    #[error("TOML Error `{0}`")]
+    TomlError(toml::de::Error),
+    #[error("Unknown format `{0}`")]
+    UnknownFormat(String),
+    #[error("Decompress Error `{0}`")]
+    DecompressError(io::Error),
+    #[error("Empty file `{0}`")]
+    EmptyFile(String),
+    #[error("Config Error `{0}`")]
+    ConfigError(String),
+    #[error("Unknown device `{0}`")]
+    UnknownDevice(String),
+    #[error("Timeout Error `{0}`")]
+    Timeout(String),
+    #[error("Command Error `{0}`")]
+    CommandError(String),
+    #[error("Stdin Error `{0}`")]
+    StdinError(String),
+    #[error("Render Error `{0}`")]
+    RenderError(String),
+    #[cfg(feature = "pcap")]
+    #[error("Capture Error `{0}`")]
+    CaptureError(String),
+    #[cfg(feature = "http")]
+    #[error("HTTP Error `{0}`")]
+    HttpError(String),
+    #[cfg(feature = "snmp")]
+    #[error("SNMP Error `{0}`")]
+    SnmpError(String),
+    #[cfg(feature = "ssh")]
+    #[error("SSH Error `{0}`")]
+    SshError(String),
 }
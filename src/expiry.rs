@@ -2,13 +2,25 @@ use std::{
     borrow::Borrow,
     collections::HashSet,
     hash::{Hash, Hasher},
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
+use serde::{Deserialize, Serialize};
+
+/// Details some pollers report alongside the hardware they can see.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Signal strength in dBm.
+    pub signal: Option<i64>,
+    /// How long the station has been connected, in seconds.
+    pub connected_secs: Option<u64>,
+}
+
 #[derive(Eq, Clone)]
 struct ExpireItem<T> {
     item: T,
     expiry: Instant,
+    metadata: Option<Metadata>,
 }
 
 impl<T> PartialEq for ExpireItem<T>
@@ -35,6 +47,13 @@ impl<T> Borrow<T> for ExpireItem<T> {
     }
 }
 
+/// An item with its expiry as wall-clock time so it survives a restart.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PersistedItem<T> {
+    pub item: T,
+    pub expires: SystemTime,
+}
+
 #[derive(Default, Clone)]
 pub struct ExpireSet<T> {
     inner: HashSet<ExpireItem<T>>,
@@ -44,6 +63,25 @@ impl<T> ExpireSet<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.inner.iter().map(|i| &i.item)
     }
+
+    pub fn iter_with_expiry(&self) -> impl Iterator<Item = (&T, Instant)> {
+        self.inner.iter().map(|i| (&i.item, i.expiry))
+    }
+
+    pub fn to_persisted(&self) -> Vec<PersistedItem<T>>
+    where
+        T: Clone,
+    {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        self.inner
+            .iter()
+            .map(|i| PersistedItem {
+                item: i.item.clone(),
+                expires: wall + i.expiry.saturating_duration_since(now),
+            })
+            .collect()
+    }
 }
 
 impl<T> ExpireSet<T>
@@ -51,17 +89,34 @@ where
     T: Eq + Hash,
 {
     pub fn insert(&mut self, item: T, expiry: Instant) {
-        let expiry = if let Some(item) = self.inner.get(&item) {
-            if expiry > item.expiry {
-                expiry
-            } else {
-                item.expiry
-            }
+        self.insert_with_metadata(item, expiry, None);
+    }
+
+    /// Inserts an item along with details about it. Without details any
+    /// already known are kept.
+    pub fn insert_with_metadata(&mut self, item: T, expiry: Instant, metadata: Option<Metadata>) {
+        let (expiry, metadata) = if let Some(existing) = self.inner.get(&item) {
+            (
+                expiry.max(existing.expiry),
+                metadata.or_else(|| existing.metadata.clone()),
+            )
         } else {
-            expiry
+            (expiry, metadata)
         };
 
-        self.inner.replace(ExpireItem { item, expiry });
+        self.inner.replace(ExpireItem {
+            item,
+            expiry,
+            metadata,
+        });
+    }
+
+    pub fn insert_at(&mut self, item: T, now: Instant, ttl: Duration) {
+        self.insert(item, now + ttl);
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -72,13 +127,58 @@ where
         self.inner.contains(item)
     }
 
+    /// The details reported for the item, if any were.
+    pub fn metadata(&self, item: &T) -> Option<&Metadata> {
+        self.inner.get(item).and_then(|i| i.metadata.as_ref())
+    }
+
+    /// When the item expires, if it is in the set.
+    pub fn get_expiry(&self, item: &T) -> Option<Instant> {
+        self.inner.get(item).map(|i| i.expiry)
+    }
+
+    /// How long until the item expires, if it is in the set.
+    pub fn remaining(&self, item: &T, now: Instant) -> Option<Duration> {
+        self.get_expiry(item)
+            .map(|expiry| expiry.saturating_duration_since(now))
+    }
+
     pub fn remove(&mut self, item: &T) -> bool {
         self.inner.remove(item)
     }
 
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Keeps only the items the predicate accepts, leaving their expiry alone.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.inner.retain(|i| keep(&i.item));
+    }
+
+    /// Rebuilds a set from persisted items, dropping those already expired.
+    pub fn from_persisted(items: Vec<PersistedItem<T>>) -> Self {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let mut set = ExpireSet {
+            inner: HashSet::new(),
+        };
+        for item in items {
+            if let Ok(remaining) = item.expires.duration_since(wall) {
+                if !remaining.is_zero() {
+                    set.insert(item.item, now + remaining);
+                }
+            }
+        }
+        set
+    }
+
     pub fn extend_from(&mut self, other: ExpireSet<T>) {
         for item in other.inner {
-            self.insert(item.item, item.expiry);
+            self.insert_with_metadata(item.item, item.expiry, item.metadata);
         }
     }
 
@@ -86,7 +186,7 @@ where
         let now = Instant::now();
         let mut newset: HashSet<ExpireItem<T>> = HashSet::new();
         newset.reserve(self.inner.len());
-        newset.extend(self.inner.drain().filter(|ei| ei.expiry < now));
+        newset.extend(self.inner.drain().filter(|ei| ei.expiry > now));
         self.inner = newset;
     }
 }
@@ -99,3 +199,51 @@ where
         set.inner.into_iter().map(|i| i.item).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::ExpireSet;
+
+    #[test]
+    fn expire_keeps_future_items() {
+        let now = Instant::now();
+        let mut set = ExpireSet::default();
+        set.insert("stale", now - Duration::from_secs(10));
+        set.insert("fresh", now + Duration::from_secs(60));
+
+        set.expire();
+
+        assert!(set.contains(&"fresh"));
+        assert!(!set.contains(&"stale"));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn insert_at_expires_relative_to_the_reference() {
+        let reference = Instant::now() + Duration::from_secs(3600);
+        let ttl = Duration::from_secs(30);
+        let mut set = ExpireSet::default();
+        set.insert_at("mac", reference, ttl);
+
+        assert_eq!(set.get_expiry(&"mac"), Some(reference + ttl));
+        assert_eq!(set.remaining(&"mac", reference), Some(ttl));
+    }
+
+    #[test]
+    fn retain_keeps_the_expiry_of_retained_items() {
+        let now = Instant::now();
+        let mut set = ExpireSet::default();
+        set.insert(1, now + Duration::from_secs(10));
+        set.insert(2, now + Duration::from_secs(20));
+        set.insert(3, now + Duration::from_secs(30));
+
+        set.retain(|item| item % 2 == 1);
+
+        assert_eq!(set.len(), 2);
+        assert!(!set.contains(&2));
+        assert_eq!(set.get_expiry(&1), Some(now + Duration::from_secs(10)));
+        assert_eq!(set.get_expiry(&3), Some(now + Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr};
+
+use eui48::MacAddress;
+use serde::{Deserialize, Serialize};
+
+/// Matches hardware addresses against a pattern. Patterns may be a full MAC,
+/// a prefix ending in `*` such as `aa:bb:cc:*`, or a MAC with a mask length
+/// such as `aa:bb:cc:00:00:00/24`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct MacPattern {
+    bytes: [u8; 6],
+    bits: u8,
+}
+
+impl MacPattern {
+    pub fn matches(&self, mac: &MacAddress) -> bool {
+        let bytes = mac.to_array();
+        let mut remaining = self.bits;
+
+        for (pattern, byte) in self.bytes.iter().zip(bytes.iter()) {
+            if remaining == 0 {
+                break;
+            }
+
+            let mask = if remaining >= 8 {
+                0xff
+            } else {
+                0xffu8 << (8 - remaining)
+            };
+
+            if pattern & mask != byte & mask {
+                return false;
+            }
+
+            remaining = remaining.saturating_sub(8);
+        }
+
+        true
+    }
+}
+
+impl FromStr for MacPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid MAC pattern `{}`", pattern);
+
+        if let Some((mac, bits)) = pattern.split_once('/') {
+            let mac = MacAddress::parse_str(mac).map_err(|_| invalid())?;
+            let bits: u8 = bits.parse().map_err(|_| invalid())?;
+            if bits > 48 {
+                return Err(invalid());
+            }
+
+            return Ok(MacPattern {
+                bytes: mac.to_array(),
+                bits,
+            });
+        }
+
+        if let Some(prefix) = pattern.strip_suffix('*') {
+            let prefix = prefix.trim_end_matches([':', '-']);
+            let mut bytes = [0; 6];
+            let mut count = 0;
+
+            if !prefix.is_empty() {
+                for part in prefix.split([':', '-']) {
+                    if count >= bytes.len() || part.len() != 2 {
+                        return Err(invalid());
+                    }
+                    bytes[count] = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+                    count += 1;
+                }
+            }
+
+            return Ok(MacPattern {
+                bytes,
+                bits: (count * 8) as u8,
+            });
+        }
+
+        let mac = MacAddress::parse_str(pattern).map_err(|_| invalid())?;
+        Ok(MacPattern {
+            bytes: mac.to_array(),
+            bits: 48,
+        })
+    }
+}
+
+impl TryFrom<String> for MacPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+impl fmt::Display for MacPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mac = MacAddress::new(self.bytes);
+        write!(f, "{}/{}", mac.to_hex_string(), self.bits)
+    }
+}
+
+impl From<MacPattern> for String {
+    fn from(pattern: MacPattern) -> Self {
+        pattern.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eui48::MacAddress;
+
+    use super::MacPattern;
+
+    fn matches(pattern: &str, mac: &str) -> bool {
+        let pattern: MacPattern = pattern.parse().unwrap();
+        pattern.matches(&MacAddress::parse_str(mac).unwrap())
+    }
+
+    #[test]
+    fn masks_match_their_prefix() {
+        assert!(matches("aa:bb:cc:00:00:00/24", "aa:bb:cc:12:34:56"));
+        assert!(!matches("aa:bb:cc:00:00:00/24", "aa:bb:cd:12:34:56"));
+
+        assert!(matches("aa:bb:cc:d0:00:00/28", "aa:bb:cc:d0:00:01"));
+        assert!(matches("aa:bb:cc:d0:00:00/28", "aa:bb:cc:df:ff:ff"));
+        assert!(!matches("aa:bb:cc:d0:00:00/28", "aa:bb:cc:e0:00:00"));
+        assert!(!matches("aa:bb:cc:d0:00:00/28", "aa:bb:cc:c0:00:00"));
+    }
+
+    #[test]
+    fn invalid_masks_are_rejected() {
+        assert!("aa:bb:cc:00:00:00/49".parse::<MacPattern>().is_err());
+        assert!("aa:bb:cc/24".parse::<MacPattern>().is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::layout::{Layout, NodeKind};
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn kind_name(kind: NodeKind) -> &'static str {
+    match kind {
+        NodeKind::Device => "device",
+        NodeKind::Port => "port",
+        NodeKind::Client => "client",
+        NodeKind::Aggregate => "aggregate",
+    }
+}
+
+/// Writes the layout as GraphML, groups become nodes holding a nested graph.
+pub fn render(layout: &Layout) -> String {
+    let mut output = String::new();
+    output.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    output
+        .push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    output.push_str("  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n");
+    output.push_str("  <graph id=\"G\" edgedefault=\"undirected\">\n");
+
+    let write_node = |output: &mut String, index: usize, indent: &str| {
+        let node = &layout.nodes[index];
+        output.push_str(&format!(
+            "{}<node id=\"n{}\">\n{}  <data key=\"label\">{}</data>\n{}  <data key=\"kind\">{}</data>\n{}</node>\n",
+            indent,
+            index,
+            indent,
+            escape(&node.label),
+            indent,
+            kind_name(node.kind),
+            indent
+        ));
+    };
+
+    for (group, label) in layout.groups.iter().enumerate() {
+        output.push_str(&format!("    <node id=\"g{}\">\n", group));
+        output.push_str(&format!(
+            "      <data key=\"label\">{}</data>\n",
+            escape(label)
+        ));
+        output.push_str(&format!(
+            "      <graph id=\"g{}:\" edgedefault=\"undirected\">\n",
+            group
+        ));
+        for (index, _) in layout
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.group == Some(group))
+        {
+            write_node(&mut output, index, "        ");
+        }
+        output.push_str("      </graph>\n    </node>\n");
+    }
+
+    for (index, _) in layout
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.group.is_none())
+    {
+        write_node(&mut output, index, "    ");
+    }
+
+    for (i, (source, target)) in layout.edges.iter().enumerate() {
+        output.push_str(&format!(
+            "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\"/>\n",
+            i, source, target
+        ));
+    }
+
+    output.push_str("  </graph>\n</graphml>\n");
+    output
+}
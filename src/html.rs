@@ -0,0 +1,101 @@
+/// Wraps a rendered SVG map and its topology into a standalone page.
+pub fn page(svg: &str, topology: &str) -> String {
+    // Stop the embedded JSON from closing its script element early.
+    let topology = topology.replace("</", "<\\/");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Network Map</title>
+<style>
+body {{ font-family: sans-serif; margin: 0; }}
+#controls {{ padding: 8px; border-bottom: 1px solid #ccc; }}
+#controls label {{ margin-right: 12px; }}
+.hidden {{ display: none; }}
+.match ellipse, .match polygon, .match path {{ stroke: red; stroke-width: 3; }}
+</style>
+</head>
+<body>
+<div id="controls">
+<input id="search" type="search" placeholder="Search for a MAC">
+<span id="types"></span>
+</div>
+<div id="map">
+{svg}
+</div>
+<script type="application/json" id="topology">{topology}</script>
+<script>
+(function() {{
+  var topology = JSON.parse(document.getElementById("topology").textContent);
+
+  var types = {{}};
+  topology.devices.forEach(function(device) {{
+    types[device.type] = true;
+  }});
+
+  var container = document.getElementById("types");
+  Object.keys(types).sort().forEach(function(type) {{
+    var label = document.createElement("label");
+    var input = document.createElement("input");
+    input.type = "checkbox";
+    input.checked = true;
+    input.addEventListener("change", function() {{
+      document.querySelectorAll(".type-" + type).forEach(function(node) {{
+        node.classList.toggle("hidden", !input.checked);
+      }});
+    }});
+    label.appendChild(input);
+    label.appendChild(document.createTextNode(" " + type));
+    container.appendChild(label);
+  }});
+
+  function highlight(id, on) {{
+    var node = document.getElementById(id);
+    if (node) {{
+      node.classList.toggle("match", on);
+    }}
+  }}
+
+  document.getElementById("search").addEventListener("input", function(event) {{
+    var query = event.target.value.trim().toLowerCase();
+    topology.devices.forEach(function(device) {{
+      var found = query.length > 0 && device.mac.some(function(mac) {{
+        return mac.indexOf(query) >= 0;
+      }});
+      highlight("device-" + device.id, found);
+
+      device.ports.forEach(function(port) {{
+        var seen = query.length > 0 && port.macs.some(function(mac) {{
+          return mac.indexOf(query) >= 0;
+        }});
+        highlight("port-" + device.id + "-" + port.id, seen);
+      }});
+    }});
+  }});
+}})();
+</script>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::page;
+
+    #[test]
+    fn page_embeds_the_svg_and_topology() {
+        let html = page(
+            r#"<svg width="10pt"><g id="node1"></g></svg>"#,
+            r#"{"devices":[{"id":"sw","name":"</script>"}],"links":[]}"#,
+        );
+
+        assert!(html.contains(r#"<svg width="10pt"><g id="node1"></g></svg>"#));
+        assert!(html.contains(
+            r#"<script type="application/json" id="topology">{"devices":[{"id":"sw","name":"<\/script>"}],"links":[]}</script>"#
+        ));
+    }
+}
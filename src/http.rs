@@ -0,0 +1,29 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::error::Error;
+
+/// Fetches a document over HTTP, sending the given headers along with the
+/// request.
+pub fn get(
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> Result<String, Error> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let mut request = agent.get(url);
+    for (name, value) in headers.iter() {
+        request = request.set(name, value);
+    }
+
+    match request.call() {
+        Ok(response) => response.into_string().map_err(Error::IoError),
+        Err(ureq::Error::Status(status, _)) => Err(Error::HttpError(format!(
+            "{} responded with status {}",
+            url, status
+        ))),
+        Err(ureq::Error::Transport(error)) => Err(Error::HttpError(format!(
+            "{} could not be fetched: {}",
+            url, error
+        ))),
+    }
+}
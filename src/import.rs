@@ -0,0 +1,210 @@
+use std::{fs, path::Path, str::FromStr};
+
+use eui48::MacAddress;
+
+use crate::{
+    error::Error,
+    parsers::{DeviceDataFormat, DeviceDataOptions, DevicePoller, PortDataFormat, PortPoller},
+    DeviceConfig, NetworkConfig, PortConfig,
+};
+
+fn device_format(extension: &str) -> Option<DeviceDataFormat> {
+    match extension {
+        "fdb" => Some(DeviceDataFormat::ForwardDb),
+        "swc" => Some(DeviceDataFormat::SwConfig),
+        "arp" => Some(DeviceDataFormat::ProcArp),
+        "neigh" => Some(DeviceDataFormat::IpNeigh),
+        _ => None,
+    }
+}
+
+fn port_format(extension: &str) -> Option<PortDataFormat> {
+    match extension {
+        "hostapd" => Some(PortDataFormat::HostApd),
+        "json" => Some(PortDataFormat::HostApdJson),
+        _ => None,
+    }
+}
+
+/// Reads hardware addresses listed one per line.
+fn read_macs(path: &Path) -> Result<Vec<MacAddress>, Error> {
+    let data = fs::read_to_string(path).map_err(Error::IoError)?;
+    Ok(data
+        .lines()
+        .filter_map(|line| MacAddress::from_str(line.trim()).ok())
+        .collect())
+}
+
+/// Replaces the pollers of each device with the dumps found in a directory.
+///
+/// Every subdirectory is a device named after it. Files with a device format
+/// as their extension (`fdb`, `swc`, `arp`, `neigh`) are read as device data,
+/// files with a port format (`hostapd`, `json`) are read as data for the port
+/// named after the file. Devices not already configured get their hardware
+/// from a `mac` file in their directory and gain ports as their dumps report
+/// them.
+pub fn import_dir(config: &mut NetworkConfig, dir: &Path) -> Result<(), Error> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .map_err(Error::IoError)?
+        .collect::<Result<_, _>>()
+        .map_err(Error::IoError)?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        let id = entry.file_name().to_string_lossy().into_owned();
+        let index = match config.devices.iter().position(|d| d.id == id) {
+            Some(index) => index,
+            None => {
+                let mac_file = path.join("mac");
+                let mac = if mac_file.exists() {
+                    read_macs(&mac_file)?
+                } else {
+                    Vec::new()
+                };
+
+                config.devices.push(DeviceConfig {
+                    id: id.clone(),
+                    name: None,
+                    device_type: Default::default(),
+                    ip: None,
+                    mac,
+                    ports: Vec::new(),
+                    pollers: Vec::new(),
+                    case_insensitive_ports: false,
+                    strip_vlan_suffix: false,
+                    auto_ports: true,
+                    enabled: None,
+                    root: None,
+                });
+                config.devices.len() - 1
+            }
+        };
+
+        let device = &mut config.devices[index];
+        device.pollers.clear();
+        for port in device.ports.iter_mut() {
+            port.pollers.clear();
+        }
+
+        let mut files: Vec<_> = fs::read_dir(&path)
+            .map_err(Error::IoError)?
+            .collect::<Result<_, _>>()
+            .map_err(Error::IoError)?;
+        files.sort_by_key(|e| e.file_name());
+
+        for file in files {
+            let file = file.path();
+            let extension = match file.extension() {
+                Some(extension) => extension.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let file_name = file.to_string_lossy().into_owned();
+
+            if let Some(format) = device_format(&extension) {
+                log::trace!("importing {} as device data for {}", file_name, id);
+                device.pollers.push(DevicePoller::File {
+                    file: file_name,
+                    format,
+                    tail: false,
+                    expiry_secs: None,
+                    timeout_secs: None,
+                    options: DeviceDataOptions {
+                        learn_self_macs: true,
+                        ..Default::default()
+                    },
+                });
+            } else if let Some(format) = port_format(&extension) {
+                let port_id = file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                log::trace!("importing {} as data for {} {}", file_name, id, port_id);
+
+                let poller = PortPoller::File {
+                    file: file_name,
+                    format,
+                    tail: false,
+                    expiry_secs: None,
+                    timeout_secs: None,
+                };
+                match device.ports.iter_mut().find(|p| p.id == port_id) {
+                    Some(port) => port.pollers.push(poller),
+                    None => device.ports.push(PortConfig {
+                        id: port_id,
+                        name: None,
+                        pollers: vec![poller],
+                        bond: None,
+                        vlans: Vec::new(),
+                        ssid: None,
+                    }),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::Network;
+
+    #[test]
+    fn directories_of_dumps_are_imported() {
+        let root = std::env::temp_dir().join(format!("netmap-import-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (file, contents) in [
+            ("sw/mac", "00:11:22:33:44:01\n"),
+            (
+                "sw/bridge.fdb",
+                "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:03 dev lan2\n",
+            ),
+            ("ap/mac", "00:11:22:33:44:03\n"),
+            ("ap/wlan0.hostapd", "00:11:22:33:44:31\n"),
+            ("r/mac", "00:11:22:33:44:02\n"),
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let mut config = NetworkConfig::default();
+        import_dir(&mut config, &root).unwrap();
+        let ids: Vec<&str> = config.devices.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["ap", "r", "sw"]);
+
+        let mut network = Network::new(config, &root);
+        network.poll().unwrap();
+        let links: Vec<(String, Option<String>, String, Option<String>)> = network
+            .links()
+            .into_iter()
+            .map(|l| (l.left, l.left_port, l.right, l.right_port))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "ap".to_owned(),
+                    None,
+                    "sw".to_owned(),
+                    Some("lan2".to_owned())
+                ),
+                (
+                    "r".to_owned(),
+                    None,
+                    "sw".to_owned(),
+                    Some("lan1".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(network.unknown_summary().count, 1);
+    }
+}
@@ -0,0 +1,33 @@
+/// What a map draws, independent of the format it is drawn in.
+#[derive(Default)]
+pub struct Layout {
+    /// The labels of the groups gathering a device together with its ports.
+    pub groups: Vec<String>,
+    pub nodes: Vec<LayoutNode>,
+    /// Pairs of indexes into the nodes.
+    pub edges: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Device,
+    Port,
+    /// A single client that isn't a known device.
+    Client,
+    /// Some number of clients that aren't known devices.
+    Aggregate,
+}
+
+pub struct LayoutNode {
+    pub label: String,
+    pub kind: NodeKind,
+    /// The index of the group the node belongs to.
+    pub group: Option<usize>,
+}
+
+impl Layout {
+    pub fn add_node(&mut self, label: String, kind: NodeKind, group: Option<usize>) -> usize {
+        self.nodes.push(LayoutNode { label, kind, group });
+        self.nodes.len() - 1
+    }
+}
@@ -1,32 +1,87 @@
+mod builder;
+#[cfg(feature = "pcap")]
+mod capture;
+mod command;
+mod dns;
 mod error;
 mod expiry;
+mod filter;
+mod graphml;
+mod html;
+#[cfg(feature = "http")]
+mod http;
+mod import;
+mod layout;
+mod mermaid;
 mod multimap;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod oui;
 mod parsers;
+mod render;
+mod schedule;
+mod snapshot;
+#[cfg(feature = "snmp")]
+mod snmp;
+#[cfg(feature = "ssh")]
+mod ssh;
+mod template;
+mod verify;
 
 use std::{
-    collections::HashMap,
-    fs::File,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fs::{self, File},
     io::BufReader,
+    net::IpAddr,
     path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
 };
 
-use dot_writer::{Attributes, DotWriter, NodeId};
-use error::Error;
+pub use builder::NetworkBuilder;
+use dot_writer::{Attributes, DotWriter, Node, NodeId, Scope};
+pub use error::Error;
 use eui48::MacAddress;
-use expiry::ExpireSet;
+pub use expiry::{ExpireSet, Metadata, PersistedItem};
+pub use filter::MacPattern;
+use layout::{Layout, NodeKind};
 use multimap::MultiMap;
-use parsers::{DevicePoller, PortPoller};
-use serde::Deserialize;
+pub use parsers::{
+    detect_format, DeviceData, DeviceDataFormat, DeviceDataOptions, DevicePoller, PortDataFormat,
+    PortPoller, Source,
+};
+use parsers::{Context, Formats};
+pub use schedule::Scheduler;
+use serde::{Deserialize, Serialize};
+pub use snapshot::{Move, Snapshot, TopologyDiff};
+pub use verify::{Discrepancy, ExpectedTopology};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PortConfig {
     pub id: String,
     pub name: Option<String>,
     #[serde(default)]
     pollers: Vec<PortPoller>,
+    /// Ports in the same bond are treated as a single logical port.
+    #[serde(default)]
+    pub bond: Option<String>,
+    /// The VLANs the port carries.
+    #[serde(default)]
+    pub vlans: Vec<u16>,
+    /// The wireless network served on this interface, shown in place of the
+    /// port's name.
+    #[serde(default)]
+    pub ssid: Option<String>,
 }
 
-#[derive(Deserialize, Default, Clone)]
+impl PortConfig {
+    /// The id of the port this port's visibility is recorded on.
+    fn key(&self) -> &str {
+        self.bond.as_ref().unwrap_or(&self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
 #[serde(rename = "lowercase")]
 pub enum DeviceType {
     Router,
@@ -37,35 +92,585 @@ pub enum DeviceType {
     Unknown,
 }
 
-#[derive(Deserialize)]
+impl DeviceType {
+    fn name(&self) -> &'static str {
+        match self {
+            DeviceType::Router => "router",
+            DeviceType::Switch => "switch",
+            DeviceType::Modem => "modem",
+            DeviceType::AP => "ap",
+            DeviceType::Unknown => "unknown",
+        }
+    }
+
+    fn shape(&self) -> &'static str {
+        match self {
+            DeviceType::Router => "box",
+            DeviceType::Switch => "box3d",
+            DeviceType::Modem => "cylinder",
+            DeviceType::AP => "house",
+            DeviceType::Unknown => "ellipse",
+        }
+    }
+
+    fn fill_color(&self) -> &'static str {
+        match self {
+            DeviceType::Router => "lightblue",
+            DeviceType::Switch => "palegreen",
+            DeviceType::Modem => "wheat",
+            DeviceType::AP => "plum",
+            DeviceType::Unknown => "white",
+        }
+    }
+
+    const ALL: [DeviceType; 5] = [
+        DeviceType::Router,
+        DeviceType::Switch,
+        DeviceType::Modem,
+        DeviceType::AP,
+        DeviceType::Unknown,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DeviceConfig {
     pub id: String,
     pub name: Option<String>,
     #[serde(default, rename = "type")]
     pub device_type: DeviceType,
+    #[serde(default)]
+    pub ip: Option<String>,
     pub mac: Vec<MacAddress>,
     #[serde(default)]
     pub ports: Vec<PortConfig>,
     #[serde(default)]
     pollers: Vec<DevicePoller>,
+    /// Matches the port names pollers report regardless of case.
+    #[serde(default)]
+    pub case_insensitive_ports: bool,
+    /// Records hardware reported on VLAN sub-interfaces (`eth0.100`) on the
+    /// parent port.
+    #[serde(default)]
+    pub strip_vlan_suffix: bool,
+    /// Adds any ports the pollers report that aren't configured.
+    #[serde(default)]
+    pub auto_ports: bool,
+    /// Disabled devices are neither polled nor drawn, defaults to enabled.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// Where the device's pollers find their files when it was loaded from a
+    /// different config file to the rest of the network.
+    #[serde(skip)]
+    root: Option<PathBuf>,
+}
+
+impl DeviceConfig {
+    fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct MapOptions {
+    /// The number of recent polls a MAC is remembered for, regardless of expiry.
+    pub window: usize,
+    /// Keeps everything observed for the life of the network instead of expiring it.
+    pub snapshot: bool,
+    /// Removes a device's own hardware from its ports' visibility after polling.
+    pub strip_own_macs: bool,
+    /// The number of visible ports a device needs before its ports are drawn.
+    pub cluster_min_ports: usize,
+    /// Draws devices as records with a field for each visible port.
+    pub record_nodes: bool,
+    /// The label for device nodes, `{name}`, `{id}`, `{type}`, `{ip}` and
+    /// `{mac}` are replaced with the device's details.
+    pub device_label_template: String,
+    /// Includes the number of visible hardware in port labels.
+    pub port_counts: bool,
+    /// Colours port nodes by the VLAN they carry, ports carrying several
+    /// VLANs are grey.
+    pub color_by_vlan: bool,
+    /// Draws devices whose pollers fail greyed out.
+    pub show_down: bool,
+    /// Draws links that only one of the devices can see as dashed.
+    pub mark_asymmetric: bool,
+    /// Leaves out port nodes for ports with only one neighbour.
+    pub compact: bool,
+    /// Lists each port's hardware in the JSON map along with how long until
+    /// it expires.
+    pub include_macs: bool,
+    /// Only draws what changed since the map was last drawn.
+    pub delta: bool,
+    /// Sizes the nodes standing for unknown devices by how many there are.
+    pub scale_aggregates: bool,
+    /// Gathers the unknown devices on all of a device's ports into one node
+    /// attached to the device.
+    pub aggregate_per_device: bool,
+    /// Keeps what a port could last see when its poller reads an empty file
+    /// instead of letting it expire.
+    pub preserve_on_empty: bool,
+    /// Looks up the hostnames of unknown hardware whose addresses neighbour
+    /// tables know and draws them as their own nodes.
+    pub reverse_dns: bool,
+    /// Adds a cluster showing how each type of device is drawn.
+    pub legend: bool,
+    /// How long each poller without its own `timeout_secs` may take when
+    /// polling asynchronously, defaults to 10 seconds.
+    pub poller_timeout_secs: Option<u64>,
+    /// Adds when the unknown devices were last seen to their nodes and colours
+    /// their edges from green when fresh to red when about to expire.
+    pub show_ages: bool,
+    /// Leaves IPv6 link-local addresses, `fe80::/10`, out of labels.
+    pub hide_link_local: bool,
+    pub graph: GraphOptions,
+}
+
+/// Graphviz attributes for the whole map, those left unset use Graphviz's
+/// defaults.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct GraphOptions {
+    /// The direction ranks are laid out in, like `LR` or `TB`.
+    pub rankdir: Option<String>,
+    /// The space between ranks, in inches.
+    pub ranksep: Option<f64>,
+    /// The space between nodes in the same rank, in inches.
+    pub nodesep: Option<f64>,
+    /// How edges are drawn, like `ortho` or `polyline`.
+    pub splines: Option<String>,
+    /// The font for the graph's label and every node.
+    pub fontname: Option<String>,
+    /// A title drawn beneath the map.
+    pub label: Option<String>,
+    /// How the ports at either end of each link are shown.
+    pub edge_ports: EdgePorts,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgePorts {
+    Hidden,
+    /// Names the ports in a tooltip on the edge.
+    #[default]
+    Tooltip,
+    /// Labels each end of the edge with its port as well as the tooltip.
+    Labels,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct NetworkConfig {
     pub devices: Vec<DeviceConfig>,
+    #[serde(default)]
+    pub options: MapOptions,
+    /// Hardware that should never be considered visible.
+    #[serde(default)]
+    pub ignore: Vec<MacPattern>,
+    /// When not empty only matching hardware is considered visible.
+    #[serde(default)]
+    pub allow: Vec<MacPattern>,
+    /// Individual hardware that should never be considered visible.
+    #[serde(default)]
+    pub ignore_macs: Vec<MacAddress>,
+    /// OUIs, like `00:11:22`, whose hardware should never be considered visible.
+    #[serde(default)]
+    pub ignore_prefixes: Vec<String>,
+    /// Ignores locally administered hardware, which is usually a phone
+    /// randomising its address for privacy rather than noise.
+    #[serde(default)]
+    pub ignore_local_macs: bool,
+    #[serde(default)]
+    pub expected: ExpectedTopology,
+    /// Vendor names keyed by OUI, like `00:11:22`, extending the built in list.
+    #[serde(default)]
+    pub vendors: BTreeMap<String, String>,
+    /// dnsmasq lease files naming hardware that isn't a known device.
+    #[serde(default)]
+    pub leases: Vec<String>,
+    /// Hardware several devices may declare, like a virtual gateway address.
+    #[serde(default)]
+    pub shared_macs: Vec<MacAddress>,
+    /// How long hardware stays visible for pollers that don't set their own
+    /// expiry.
+    #[serde(default)]
+    pub default_expiry_secs: Option<u64>,
+}
+
+impl NetworkConfig {
+    /// Loads the config, reading YAML or TOML when the file's extension says
+    /// so and JSON otherwise.
+    pub fn load(config_file: &Path) -> Result<Self, Error> {
+        let extension = config_file
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+
+        let mut value: serde_json::Value = match extension.as_deref() {
+            Some("yaml") | Some("yml") => {
+                let file = File::open(config_file).map_err(Error::IoError)?;
+                serde_yaml::from_reader(BufReader::new(file)).map_err(Error::YamlError)?
+            }
+            Some("toml") => {
+                let data = fs::read_to_string(config_file).map_err(Error::IoError)?;
+                toml::from_str(&data).map_err(Error::TomlError)?
+            }
+            _ => {
+                let file = File::open(config_file).map_err(Error::IoError)?;
+                serde_json::from_reader(BufReader::new(file)).map_err(Error::ParseError)?
+            }
+        };
+        template::expand(&mut value);
+        serde_json::from_value(value).map_err(Error::ParseError)
+    }
+
+    /// Checks for devices without hardware, hardware declared by more than
+    /// one device and expected links through ports that aren't declared.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems: Vec<String> = Vec::new();
+        let devices: Vec<&DeviceConfig> = self.devices.iter().filter(|d| d.is_enabled()).collect();
+
+        let mut owners: BTreeMap<MacAddress, Vec<&str>> = BTreeMap::new();
+        for device in devices.iter() {
+            if device.mac.is_empty() {
+                problems.push(format!("{} declares no hardware", device.id));
+            }
+            for mac in device.mac.iter() {
+                let owners = owners.entry(*mac).or_default();
+                if !owners.contains(&device.id.as_str()) {
+                    owners.push(&device.id);
+                }
+            }
+        }
+        for (mac, owners) in owners.iter() {
+            if owners.len() > 1 && !self.shared_macs.contains(mac) {
+                problems.push(format!(
+                    "hardware {} is declared by {}",
+                    mac,
+                    owners.join(" and ")
+                ));
+            }
+        }
+
+        for prefix in self.ignore_prefixes.iter() {
+            if !oui::is_prefix(prefix) {
+                problems.push(format!("ignored prefix {} is not an OUI", prefix));
+            }
+        }
+
+        for link in self.expected.links.iter() {
+            let ends = [
+                (&link.left, &link.left_port),
+                (&link.right, &link.right_port),
+            ];
+            for (id, port) in ends {
+                let device = match devices.iter().find(|d| d.id == *id) {
+                    Some(device) => device,
+                    None => {
+                        problems.push(format!("expected link to unknown device {}", id));
+                        continue;
+                    }
+                };
+                if let Some(port) = port {
+                    let declared = device
+                        .ports
+                        .iter()
+                        .any(|p| p.id == *port || p.key() == port);
+                    if !declared && !device.auto_ports {
+                        problems.push(format!(
+                            "expected link through undeclared port {} of {}",
+                            port, id
+                        ));
+                    }
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ConfigError(problems.join(", ")))
+        }
+    }
+
+    /// Loads several config files, each device's pollers reading from beside
+    /// the file it came from. Everything other than the devices comes from
+    /// the first file.
+    pub fn load_many(config_files: &[&Path]) -> Result<Self, Error> {
+        let (first, rest) = config_files
+            .split_first()
+            .ok_or_else(|| Error::ConfigError("no config files given".to_owned()))?;
+
+        let mut config = NetworkConfig::load(first)?;
+        let mut sources: HashMap<String, &Path> = config
+            .devices
+            .iter()
+            .map(|d| (d.id.clone(), *first))
+            .collect();
+
+        for config_file in rest {
+            let other = NetworkConfig::load(config_file)?;
+            for mut device in other.devices {
+                if let Some(existing) = sources.get(&device.id) {
+                    return Err(Error::ConfigError(format!(
+                        "device {} is declared in both {} and {}",
+                        device.id,
+                        existing.display(),
+                        config_file.display()
+                    )));
+                }
+
+                sources.insert(device.id.clone(), config_file);
+                device.root = config_file.parent().map(|p| p.to_owned());
+                config.devices.push(device);
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn default_expiry(&self) -> Duration {
+        self.default_expiry_secs
+            .map(Duration::from_secs)
+            .unwrap_or(parsers::DEFAULT_EXPIRY)
+    }
+
+    /// Problems with the config that don't stop the network being mapped.
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mut owners: HashMap<MacAddress, &str> = HashMap::new();
+        for device_config in self.devices.iter() {
+            if device_config.mac.is_empty() {
+                let warning = format!(
+                    "{} declares no hardware so will not be polled",
+                    device_config.id
+                );
+                log::warn!("{}", warning);
+                warnings.push(warning);
+            }
+
+            for mac in device_config
+                .mac
+                .iter()
+                .filter(|mac| !self.shared_macs.contains(mac))
+            {
+                match owners.get(mac) {
+                    Some(owner) if *owner != device_config.id => {
+                        let warning = format!(
+                            "hardware {} is declared by both {} and {}",
+                            mac, owner, device_config.id
+                        );
+                        log::warn!("{}", warning);
+                        warnings.push(warning);
+                    }
+                    Some(_) => {}
+                    None => {
+                        owners.insert(*mac, &device_config.id);
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Polls the dumps archived in a directory instead of each device's
+    /// pollers.
+    pub fn import_dir(&mut self, dir: &Path) -> Result<(), Error> {
+        import::import_dir(self, dir)
+    }
+
+    fn accepts(&self, mac: &MacAddress) -> bool {
+        if !parsers::is_valid_mac(*mac)
+            || (self.ignore_local_macs && !mac.is_universal())
+            || self.ignore_macs.contains(mac)
+            || self.ignore_prefixes.iter().any(|p| oui::has_prefix(mac, p))
+            || self.ignore.iter().any(|p| p.matches(mac))
+        {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|p| p.matches(mac))
+    }
+
+    fn filter(&self, visible: &mut ExpireSet<MacAddress>) {
+        visible.retain(|mac| self.accepts(mac));
+    }
 }
 
 #[derive(Clone)]
 pub struct Port {
     pub name: String,
     visible: ExpireSet<MacAddress>,
+    history: VecDeque<HashSet<MacAddress>>,
+    sources: HashMap<MacAddress, Source>,
+    vlans: BTreeSet<u16>,
+    /// The VLAN visible hardware was learned on, when pollers report it.
+    learned_vlans: HashMap<MacAddress, u16>,
+    /// Visible hardware that belongs to the network rather than its clients.
+    infrastructure: HashSet<MacAddress>,
+    ssid: Option<String>,
+    /// When visible hardware was last reported by a poller.
+    last_seen: HashMap<MacAddress, Instant>,
+    /// Neighbours that announced themselves on this port.
+    neighbours: ExpireSet<MacAddress>,
+    /// The ports those neighbours announced themselves from.
+    remote_ports: HashMap<MacAddress, String>,
+    /// The bridge this port is a member of, when pollers report it.
+    master: Option<String>,
 }
 
 impl Port {
-    fn from_config(config: &PortConfig) -> Self {
+    fn named(name: String) -> Self {
         Port {
-            name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+            name,
             visible: Default::default(),
+            history: VecDeque::new(),
+            sources: HashMap::new(),
+            vlans: BTreeSet::new(),
+            learned_vlans: HashMap::new(),
+            infrastructure: HashSet::new(),
+            ssid: None,
+            last_seen: HashMap::new(),
+            neighbours: ExpireSet::default(),
+            remote_ports: HashMap::new(),
+            master: None,
+        }
+    }
+
+    fn from_config(config: &PortConfig) -> Self {
+        let name = match &config.bond {
+            Some(bond) => bond.clone(),
+            None => config.name.clone().unwrap_or_else(|| config.id.clone()),
+        };
+
+        let mut port = Port::named(name);
+        port.vlans = config.vlans.iter().copied().collect();
+        port.ssid = config.ssid.clone().filter(|_| config.bond.is_none());
+        port
+    }
+
+    fn observe(&mut self, visible: ExpireSet<MacAddress>, source: &Source) {
+        let now = Instant::now();
+        for mac in visible.iter() {
+            self.sources.insert(*mac, source.clone());
+            self.last_seen.insert(*mac, now);
+        }
+        self.visible.extend_from(visible);
+    }
+
+    fn expire(&mut self) {
+        self.visible.expire();
+        let visible = &self.visible;
+        self.sources.retain(|mac, _| visible.contains(mac));
+        self.learned_vlans.retain(|mac, _| visible.contains(mac));
+        self.infrastructure.retain(|mac| visible.contains(mac));
+        let history = &self.history;
+        self.last_seen.retain(|mac, _| {
+            visible.contains(mac) || history.iter().any(|snapshot| snapshot.contains(mac))
+        });
+        self.neighbours.expire();
+        let neighbours = &self.neighbours;
+        self.remote_ports.retain(|mac, _| neighbours.contains(mac));
+    }
+
+    fn label(&self, count: bool) -> String {
+        let name = self.ssid.as_ref().unwrap_or(&self.name);
+        let mut details = Vec::new();
+        if count {
+            details.push(self.visible.len().to_string());
+        }
+        if let Some(signal) = self.signal_range() {
+            details.push(signal);
+        }
+
+        if details.is_empty() {
+            name.clone()
+        } else {
+            format!("{} ({})", name, details.join(", "))
+        }
+    }
+
+    /// The range of signal strengths reported for the visible stations.
+    fn signal_range(&self) -> Option<String> {
+        let signals: Vec<i64> = self
+            .visible
+            .iter()
+            .filter_map(|mac| self.visible.metadata(mac).and_then(|m| m.signal))
+            .collect();
+        let weakest = signals.iter().min()?;
+        let strongest = signals.iter().max()?;
+
+        if weakest == strongest {
+            Some(format!("{} dBm", weakest))
+        } else {
+            Some(format!("{} to {} dBm", weakest, strongest))
+        }
+    }
+
+    /// The VLANs the port is configured with or has learned hardware on.
+    fn carried_vlans(&self) -> BTreeSet<u16> {
+        let mut vlans = self.vlans.clone();
+        vlans.extend(self.learned_vlans.values());
+        vlans
+    }
+
+    /// The colour to draw the port based on the VLANs it carries.
+    fn vlan_color(&self) -> Option<&'static str> {
+        const COLORS: [&str; 8] = [
+            "red", "blue", "green3", "orange", "purple", "cyan3", "magenta", "gold3",
+        ];
+
+        let vlans = self.carried_vlans();
+        match vlans.len() {
+            0 => None,
+            1 => vlans
+                .iter()
+                .next()
+                .map(|vlan| COLORS[*vlan as usize % COLORS.len()]),
+            _ => Some("gray40"),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.visible.clear();
+        self.history.clear();
+        self.sources.clear();
+        self.learned_vlans.clear();
+        self.infrastructure.clear();
+        self.neighbours.clear();
+        self.last_seen.clear();
+        self.remote_ports.clear();
+    }
+
+    /// The most authoritative source that saw any of the given hardware.
+    fn source_of(&self, mac: &[MacAddress]) -> Option<&Source> {
+        mac.iter()
+            .filter(|m| self.visible.contains(m))
+            .filter_map(|m| self.sources.get(m))
+            .max_by_key(|s| s.authority())
+    }
+
+    fn record(&mut self, snapshot: HashSet<MacAddress>, window: usize) {
+        self.history.push_back(snapshot);
+        while self.history.len() > window {
+            self.history.pop_front();
+        }
+    }
+
+    fn forget(&mut self, mac: &MacAddress) {
+        self.visible.remove(mac);
+        self.sources.remove(mac);
+        self.learned_vlans.remove(mac);
+        self.infrastructure.remove(mac);
+        self.last_seen.remove(mac);
+        for snapshot in self.history.iter_mut() {
+            snapshot.remove(mac);
+        }
+    }
+
+    fn apply_window(&mut self, now: Instant) {
+        for snapshot in self.history.iter() {
+            for mac in snapshot.iter() {
+                self.visible.insert(*mac, now);
+            }
         }
     }
 
@@ -83,205 +688,3943 @@ impl Port {
 pub struct Device {
     pub id: String,
     pub name: Option<String>,
+    pub device_type: DeviceType,
+    pub ip: Option<String>,
     pub mac: Vec<MacAddress>,
     pub ports: HashMap<String, Port>,
+    /// Maps the port names pollers report to the port they are recorded on.
+    port_aliases: HashMap<String, String>,
+    case_insensitive_ports: bool,
+    strip_vlan_suffix: bool,
+    /// Why the device's pollers last failed, if they did.
+    pub last_error: Option<String>,
 }
 
 impl Device {
+    /// Finds the id of the port a poller's report should be recorded on.
+    fn port_key(&self, reported: String) -> String {
+        // VLAN sub-interfaces like `eth0.100` belong to their parent port.
+        let reported = match reported.rsplit_once('.') {
+            Some((parent, vlan))
+                if self.strip_vlan_suffix
+                    && !vlan.is_empty()
+                    && vlan.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                parent.to_owned()
+            }
+            _ => reported,
+        };
+
+        let key = if self.case_insensitive_ports {
+            reported.to_lowercase()
+        } else {
+            reported.clone()
+        };
+
+        self.port_aliases.get(&key).cloned().unwrap_or(reported)
+    }
+
     fn name(&self) -> &str {
         self.name.as_ref().unwrap_or(&self.id)
     }
 
+    /// The label to draw the device with, expanding any placeholders in the
+    /// template.
+    fn label(&self, template: &str, addresses: &[String]) -> String {
+        if template.is_empty() {
+            return self.name().to_owned();
+        }
+
+        let mac = self
+            .mac
+            .first()
+            .map(|m| m.to_hex_string())
+            .unwrap_or_default();
+        template
+            .replace("{name}", self.name())
+            .replace("{id}", &self.id)
+            .replace("{type}", self.device_type.name())
+            .replace("{ip}", &addresses.join(", "))
+            .replace("{mac}", &mac)
+            .replace('\n', "\\n")
+    }
+
     fn from_config(config: &DeviceConfig) -> Self {
+        // Bonded ports are all recorded on a single port named after the bond.
+        let mut ports = HashMap::new();
+        let mut port_aliases = HashMap::new();
+        for port_config in config.ports.iter() {
+            let id = port_config.key().to_owned();
+            let alias = if config.case_insensitive_ports {
+                port_config.id.to_lowercase()
+            } else {
+                port_config.id.clone()
+            };
+            port_aliases.insert(alias, id.clone());
+
+            ports
+                .entry(id)
+                .or_insert_with(|| Port::from_config(port_config));
+        }
+
         Device {
             id: config.id.clone(),
             name: config.name.clone(),
+            device_type: config.device_type.clone(),
+            ip: config.ip.clone(),
             mac: config.mac.clone(),
-            ports: config
-                .ports
-                .iter()
-                .map(|c| (c.id.clone(), Port::from_config(c)))
-                .collect(),
+            ports,
+            port_aliases,
+            case_insensitive_ports: config.case_insensitive_ports,
+            strip_vlan_suffix: config.strip_vlan_suffix,
+            last_error: None,
         }
     }
-}
 
-pub struct Network {
-    root: PathBuf,
-    config: NetworkConfig,
-    devices: MultiMap<MacAddress, Device>,
-}
+    /// The port seeing another device. When several do the nearest, seeing
+    /// the least other hardware, is picked, lowest id first on a tie.
+    fn port_seeing(&self, other: &Device) -> Option<(&String, &Port)> {
+        sorted_ports(self)
+            .into_iter()
+            .filter(|(_id, p)| p.can_see(&other.mac))
+            .min_by_key(|(_id, p)| p.visible.len())
+    }
 
-impl Network {
-    pub fn new(config: NetworkConfig, root: &Path) -> Self {
-        Network {
-            root: root.to_owned(),
-            devices: config
-                .devices
-                .iter()
-                .map(|c| (c.mac.clone(), Device::from_config(c)))
-                .collect(),
-            config,
+    /// Keeps what the device could see before its config was reloaded on the
+    /// ports it still has.
+    fn inherit(&mut self, previous: Device, auto_ports: bool) {
+        for (id, mut port) in previous.ports {
+            match self.ports.get_mut(&id) {
+                Some(configured) => {
+                    port.name = configured.name.clone();
+                    port.vlans = configured.vlans.clone();
+                    port.ssid = configured.ssid.clone();
+                    *configured = port;
+                }
+                None if auto_ports => {
+                    self.ports.insert(id, port);
+                }
+                None => {}
+            }
         }
+        self.last_error = previous.last_error;
     }
+}
 
-    pub fn poll(&mut self) -> Result<(), Error> {
-        for device_config in self.config.devices.iter() {
-            let device = self
-                .devices
-                .get_mut(device_config.mac.first().unwrap())
-                .unwrap();
-
-            for port_config in device_config.ports.iter() {
-                let port = device.ports.get_mut(&port_config.id).unwrap();
-                port.visible.expire();
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Link {
+    pub left: String,
+    pub left_port: Option<String>,
+    pub right: String,
+    pub right_port: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
 
-                for poller in port_config.pollers.iter() {
-                    let visible = poller.poll(&self.root)?;
-                    port.visible.extend_from(visible);
-                }
-            }
+impl Link {
+    /// Whether only one of the devices could see the other.
+    pub fn is_asymmetric(&self) -> bool {
+        self.left_port.is_none() || self.right_port.is_none()
+    }
 
-            for poller in device_config.pollers.iter() {
-                let visible = poller.poll(&self.root)?;
-                for (port, visible) in visible {
-                    if let Some(port) = device.ports.get_mut(&port) {
-                        port.visible.extend_from(visible);
-                    }
-                }
+    /// Orders the ends of the link the same way discovered links are.
+    fn normalized(&self) -> Link {
+        if self.left <= self.right {
+            self.clone()
+        } else {
+            Link {
+                left: self.right.clone(),
+                left_port: self.right_port.clone(),
+                right: self.left.clone(),
+                right_port: self.left_port.clone(),
+                source: self.source.clone(),
             }
         }
-
-        Ok(())
     }
 
-    pub fn map(&self) -> String {
-        let mut output = Vec::new();
-        {
-            let mut writer = DotWriter::from(&mut output);
-            let mut graph = writer.graph();
+    fn between(left: &Device, right: &Device) -> Option<Link> {
+        let left_port = left.port_seeing(right);
+        let right_port = right.port_seeing(left);
 
-            // First pass cleans up the visible lists to only the most adjacent.
-            let mut devices = self.devices.clone();
-            for device in devices.values_mut() {
-                for port in device.ports.values_mut() {
-                    let visible = port.visible.clone();
-                    for mac in visible.iter() {
-                        if let Some(other) = self.devices.get(mac) {
-                            for other_port in other.ports.values() {
-                                if !other_port.can_see(&device.mac) {
-                                    for mac in other_port.visible.iter() {
-                                        port.visible.remove(mac);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if left_port.is_none() && right_port.is_none() {
+            return None;
+        }
 
-            let mut device_nodes: HashMap<String, NodeId> = HashMap::new();
-            let mut port_nodes: HashMap<(String, String), NodeId> = HashMap::new();
+        let source = left_port
+            .and_then(|(_id, p)| p.source_of(&right.mac))
+            .into_iter()
+            .chain(right_port.and_then(|(_id, p)| p.source_of(&left.mac)))
+            .max_by_key(|s| s.authority())
+            .cloned();
 
-            // Now generate all the device nodes.
-            for device in devices.values() {
-                if device.ports.values().any(|p| !p.visible.is_empty()) {
-                    let mut cluster = graph.cluster();
-                    let device_id = {
-                        let mut node = cluster.node_auto();
-                        node.set_label(device.name());
-                        device_nodes.insert(device.id.clone(), node.id());
-                        node.id()
-                    };
+        Some(Link {
+            left: left.id.clone(),
+            left_port: left_port.map(|(id, _port)| id.clone()),
+            right: right.id.clone(),
+            right_port: right_port.map(|(id, _port)| id.clone()),
+            source,
+        })
+    }
+}
 
-                    for (port_id, port) in device.ports.iter() {
-                        if !port.visible.is_empty() {
-                            let port_id = {
-                                let mut node = cluster.node_auto();
-                                node.set_label(&port.name);
-                                node.set("shape", "point", false);
-                                port_nodes.insert((device.id.clone(), port_id.clone()), node.id());
-                                node.id()
-                            };
-                            cluster.edge(device_id.clone(), port_id);
-                        }
-                    }
-                } else {
-                    let mut node = graph.node_auto();
-                    node.set_label(device.name());
-                    device_nodes.insert(device.id.clone(), node.id());
-                }
-            }
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TopologyEvent {
+    LinkAdded(Link),
+    LinkRemoved(Link),
+    DeviceAppeared(String),
+    DeviceDisappeared(String),
+}
 
-            // Final pass lists all the connections
-            devices.visit_pairs(|left, right| {
-                let left_port = left
-                    .ports
-                    .iter()
-                    .find(|(_i, p)| p.can_see(&right.mac))
-                    .map(|(id, _port)| id);
-                let right_port = right
-                    .ports
-                    .iter()
-                    .find(|(_i, p)| p.can_see(&left.mac))
-                    .map(|(id, _port)| id);
+struct Topology {
+    devices: MultiMap<MacAddress, Device>,
+    links: Vec<Link>,
+}
 
-                let left_node = match left_port {
-                    None => device_nodes.get(&left.id).unwrap(),
-                    Some(port_id) => port_nodes.get(&(left.id.clone(), port_id.clone())).unwrap(),
-                };
+impl Topology {
+    /// Lists the changes between this topology and a later one.
+    fn diff(&self, after: &Topology) -> Vec<TopologyEvent> {
+        let mut events = Vec::new();
+        let before_devices = self.present_devices();
+        let after_devices = after.present_devices();
+        let before_links: HashSet<&Link> = self.links.iter().collect();
+        let after_links: HashSet<&Link> = after.links.iter().collect();
 
-                let right_node = match right_port {
-                    None => device_nodes.get(&right.id).unwrap(),
-                    Some(port_id) => port_nodes
-                        .get(&(right.id.clone(), port_id.clone()))
-                        .unwrap(),
-                };
+        for device in after_devices.difference(&before_devices) {
+            events.push(TopologyEvent::DeviceAppeared(device.clone()));
+        }
 
-                graph.edge(left_node, right_node);
-            });
+        for link in after.links.iter().filter(|l| !before_links.contains(l)) {
+            events.push(TopologyEvent::LinkAdded(link.clone()));
+        }
 
-            for device in devices.iter() {
-                for (id, port) in device.ports.iter() {
-                    if port.visible.is_empty() {
-                        continue;
-                    }
+        for link in self.links.iter().filter(|l| !after_links.contains(l)) {
+            events.push(TopologyEvent::LinkRemoved(link.clone()));
+        }
 
-                    let port_node = port_nodes.get(&(device.id.clone(), id.clone())).unwrap();
-                    let device_count = port
-                        .visible
-                        .iter()
-                        .filter(|m| !devices.contains_key(m))
-                        .count();
+        for device in before_devices.difference(&after_devices) {
+            events.push(TopologyEvent::DeviceDisappeared(device.clone()));
+        }
 
-                    if device_count == 0 {
-                        continue;
-                    }
+        events
+    }
 
-                    let other_node_id = {
-                        let mut other_node = graph.node_auto();
-                        other_node.set_label(&format!("{} devices", device_count));
-                        other_node.id()
-                    };
+    /// Devices that can see something or are seen by something.
+    fn present_devices(&self) -> HashSet<String> {
+        let mut present: HashSet<String> = self
+            .devices
+            .values()
+            .filter(|d| d.ports.values().any(|p| !p.visible.is_empty()))
+            .map(|d| d.id.clone())
+            .collect();
 
-                    graph.edge(port_node, other_node_id);
-                }
-            }
+        for link in self.links.iter() {
+            present.insert(link.left.clone());
+            present.insert(link.right.clone());
         }
 
-        String::from_utf8(output).unwrap()
+        present
     }
-}
+
+    /// Finds a set of cycles through the linked devices, one for each link
+    /// that closes a loop. Every looped link is part of at least one.
+    fn loops(&self) -> Vec<Vec<String>> {
+        let mut neighbours: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for link in self.links.iter().filter(|l| l.left != l.right) {
+            neighbours
+                .entry(&link.left)
+                .or_default()
+                .insert(&link.right);
+            neighbours
+                .entry(&link.right)
+                .or_default()
+                .insert(&link.left);
+        }
+
+        // Span the graph breadth first, every link left out of the tree
+        // closes a loop.
+        let mut parent: HashMap<&str, Option<&str>> = HashMap::new();
+        let mut depth: HashMap<&str, usize> = HashMap::new();
+        for root in neighbours.keys() {
+            if parent.contains_key(root) {
+                continue;
+            }
+            parent.insert(root, None);
+            depth.insert(root, 0);
+            let mut queue = VecDeque::from([*root]);
+            while let Some(current) = queue.pop_front() {
+                for next in neighbours[current].iter() {
+                    if !parent.contains_key(next) {
+                        parent.insert(next, Some(current));
+                        depth.insert(next, depth[current] + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        let mut loops = Vec::new();
+        for (left, others) in neighbours.iter() {
+            for right in others.iter().filter(|r| left < *r) {
+                if parent[left] == Some(*right) || parent[right] == Some(*left) {
+                    continue;
+                }
+
+                // Walk both ends up the tree until they meet.
+                let (mut a, mut b) = (*left, *right);
+                let mut up = vec![a];
+                let mut down = vec![b];
+                while a != b {
+                    if depth[a] >= depth[b] {
+                        a = parent[a].unwrap();
+                        up.push(a);
+                    } else {
+                        b = parent[b].unwrap();
+                        down.push(b);
+                    }
+                }
+                down.pop();
+                up.extend(down.into_iter().rev());
+                loops.push(up.into_iter().map(|id| id.to_owned()).collect());
+            }
+        }
+
+        loops
+    }
+
+    /// Every port that a link attaches to must still have visibility.
+    fn is_consistent(&self) -> bool {
+        let has_port = |device: &str, port: &Option<String>| match port {
+            None => true,
+            Some(port) => self.devices.values().any(|d| {
+                d.id == device
+                    && d.ports
+                        .get(port)
+                        .map(|p| !p.visible.is_empty() || !p.neighbours.is_empty())
+                        .unwrap_or(false)
+            }),
+        };
+
+        self.links.iter().all(|link| {
+            has_port(&link.left, &link.left_port) && has_port(&link.right, &link.right_port)
+        })
+    }
+}
+
+/// The hardware visible across the network that doesn't belong to a known
+/// device. Hardware seen on several ports is only counted once.
+pub struct UnknownSummary {
+    pub count: usize,
+    pub macs: BTreeSet<MacAddress>,
+}
+
+impl UnknownSummary {
+    fn from_topology(topology: &Topology) -> Self {
+        let mut macs = BTreeSet::new();
+        for device in topology.devices.iter() {
+            for port in device.ports.values() {
+                macs.extend(
+                    port.visible
+                        .iter()
+                        .filter(|m| !topology.devices.contains_key(m)),
+                );
+            }
+        }
+
+        UnknownSummary {
+            count: macs.len(),
+            macs,
+        }
+    }
+}
+
+pub struct Summary {
+    pub devices: usize,
+    pub links: usize,
+    pub unknown: usize,
+    pub warnings: Vec<String>,
+}
+
+/// The pollers that failed during a poll, along with the device each
+/// belongs to.
+#[derive(Default, Debug)]
+pub struct PollReport {
+    pub failures: Vec<(String, Error)>,
+}
+
+impl PollReport {
+    fn add(&mut self, device: &str, failures: Vec<Error>) {
+        self.failures
+            .extend(failures.into_iter().map(|e| (device.to_owned(), e)));
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The hardware visible on each port of each device at a point in time.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct NetworkState {
+    pub devices: BTreeMap<String, BTreeMap<String, BTreeSet<MacAddress>>>,
+}
+
+/// The hardware visible on each port along with when it expires, saved so a
+/// restart doesn't start from an empty map.
+#[derive(Serialize, Deserialize, Default)]
+struct SavedState {
+    devices: BTreeMap<String, BTreeMap<String, Vec<PersistedItem<MacAddress>>>>,
+}
+
+/// What each of a device's pollers found, gathered before any of it is
+/// recorded.
+#[derive(Default)]
+struct Polled {
+    ports: Vec<(String, Source, Result<ExpireSet<MacAddress>, Error>)>,
+    devices: Vec<(Source, Result<DeviceData, Error>)>,
+}
+
+pub type NameResolver = Box<dyn Fn(&MacAddress) -> Option<String>>;
+
+/// Called with what changed whenever a poll changes the topology.
+pub type ChangeHandler = Box<dyn Fn(&TopologyDiff)>;
+
+pub struct Network {
+    root: PathBuf,
+    config: NetworkConfig,
+    devices: MultiMap<MacAddress, Device>,
+    config_warnings: Vec<String>,
+    poll_warnings: Vec<String>,
+    formats: Formats,
+    reference: Option<Instant>,
+    offsets: RefCell<HashMap<PathBuf, u64>>,
+    stdin_read: Cell<bool>,
+    ip_map: HashMap<MacAddress, Vec<IpAddr>>,
+    name_resolver: Option<NameResolver>,
+    /// Hostnames from the DHCP leases.
+    lease_names: HashMap<MacAddress, String>,
+    /// Hostnames found by reverse lookups, including failed lookups so they
+    /// aren't repeated.
+    dns_names: HashMap<IpAddr, Option<String>>,
+    /// The topology the last delta map was rendered from.
+    rendered: RefCell<Option<Topology>>,
+    change_handlers: Vec<ChangeHandler>,
+    /// What the change handlers last saw.
+    last_snapshot: Option<Snapshot>,
+}
+
+impl Network {
+    pub fn new(config: NetworkConfig, root: &Path) -> Self {
+        let config_warnings = config.warnings();
+
+        Network {
+            root: root.to_owned(),
+            devices: config
+                .devices
+                .iter()
+                .filter(|c| c.is_enabled())
+                .map(|c| (c.mac.clone(), Device::from_config(c)))
+                .collect(),
+            config,
+            config_warnings,
+            poll_warnings: Vec::new(),
+            formats: Formats::default(),
+            reference: None,
+            offsets: RefCell::new(HashMap::new()),
+            stdin_read: Cell::new(false),
+            ip_map: HashMap::new(),
+            name_resolver: None,
+            lease_names: HashMap::new(),
+            dns_names: HashMap::new(),
+            rendered: RefCell::new(None),
+            change_handlers: Vec::new(),
+            last_snapshot: None,
+        }
+    }
+
+    /// Builds a network from several config files like
+    /// `NetworkConfig::load_many`.
+    pub fn try_from_many(config_files: &[&Path]) -> Result<Self, Error> {
+        let config = NetworkConfig::load_many(config_files)?;
+        config.validate()?;
+        Ok(Network::new(config, config_files[0].parent().unwrap()))
+    }
+
+    /// Switches to a new config, keeping what is visible on the ports of
+    /// devices that are still configured. Removed devices are dropped and any
+    /// new pollers are used from the next poll.
+    pub fn reload(&mut self, config: NetworkConfig) -> Result<(), Error> {
+        config.validate()?;
+
+        let mut previous: HashMap<String, Device> = HashMap::new();
+        for device in self.devices.values() {
+            previous.insert(device.id.clone(), device.clone());
+        }
+
+        self.devices = config
+            .devices
+            .iter()
+            .filter(|c| c.is_enabled())
+            .map(|c| {
+                let mut device = Device::from_config(c);
+                if let Some(previous) = previous.remove(&c.id) {
+                    device.inherit(previous, c.auto_ports);
+                }
+                (c.mac.clone(), device)
+            })
+            .collect();
+
+        for id in previous.keys() {
+            log::info!("{} was removed from the config.", id);
+        }
+
+        self.config_warnings = config.warnings();
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn register_device_format<F>(&mut self, name: &str, parser: F)
+    where
+        F: Fn(&str) -> Result<HashMap<String, ExpireSet<MacAddress>>, Error> + 'static,
+    {
+        self.formats
+            .device
+            .insert(name.to_owned(), Box::new(parser));
+    }
+
+    pub fn register_port_format<F>(&mut self, name: &str, parser: F)
+    where
+        F: Fn(&str) -> Result<ExpireSet<MacAddress>, Error> + 'static,
+    {
+        self.formats.port.insert(name.to_owned(), Box::new(parser));
+    }
+
+    pub fn dump_config(&self) -> String {
+        let mut config = self.config.clone();
+
+        // Include any hardware the devices have since reported as their own.
+        for device_config in config.devices.iter_mut() {
+            if let Some(device) = self.devices.values().find(|d| d.id == device_config.id) {
+                device_config.mac = device.mac.clone();
+            }
+        }
+
+        serde_json::to_string_pretty(&config).unwrap()
+    }
+
+    /// The ids of the devices that are polled.
+    pub fn device_ids(&self) -> Vec<String> {
+        self.config
+            .devices
+            .iter()
+            .filter(|c| c.is_enabled())
+            .map(|c| c.id.clone())
+            .collect()
+    }
+
+    /// The addresses neighbour tables have associated with some hardware.
+    pub fn addresses(&self, mac: &MacAddress) -> &[IpAddr] {
+        self.ip_map.get(mac).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    /// The addresses learned for some hardware that are worth drawing.
+    fn labelled_addresses(&self, mac: &MacAddress) -> Vec<&IpAddr> {
+        let hide_link_local = self.config.options.hide_link_local;
+        self.addresses(mac)
+            .iter()
+            .filter(|address| !(hide_link_local && is_link_local(address)))
+            .collect()
+    }
+
+    /// The configured address of a device followed by any learned for its
+    /// hardware.
+    fn addresses_of(&self, device: &Device) -> Vec<String> {
+        let mut addresses: Vec<String> = device.ip.iter().cloned().collect();
+        for address in device.mac.iter().flat_map(|m| self.labelled_addresses(m)) {
+            let address = address.to_string();
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        addresses
+    }
+
+    /// The hardware each device is known by, for tracking down hardware
+    /// that resolves to the wrong device.
+    pub fn known_macs(&self) -> Vec<(String, Vec<MacAddress>)> {
+        let mut known: Vec<(String, Vec<MacAddress>)> = self
+            .devices
+            .entries()
+            .map(|(macs, device)| {
+                let mut macs: Vec<MacAddress> = macs.into_iter().copied().collect();
+                macs.sort();
+                (device.id.clone(), macs)
+            })
+            .collect();
+        known.sort();
+        known
+    }
+
+    pub fn config(&self) -> &NetworkConfig {
+        &self.config
+    }
+
+    pub fn into_config(self) -> NetworkConfig {
+        self.config
+    }
+
+    pub fn options(&self) -> &MapOptions {
+        &self.config.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut MapOptions {
+        &mut self.config.options
+    }
+
+    /// Names hardware that doesn't belong to a known device when drawing the
+    /// map.
+    pub fn set_name_resolver(&mut self, resolver: NameResolver) {
+        self.name_resolver = Some(resolver);
+    }
+
+    /// Calls the handler at the end of every poll that changes the links or
+    /// where hardware is plugged in.
+    pub fn on_change(&mut self, handler: ChangeHandler) {
+        if self.last_snapshot.is_none() {
+            self.last_snapshot = Some(self.snapshot());
+        }
+        self.change_handlers.push(handler);
+    }
+
+    fn notify_changes(&mut self) {
+        if self.change_handlers.is_empty() {
+            return;
+        }
+
+        let latest = self.snapshot();
+        if let Some(previous) = &self.last_snapshot {
+            let diff = previous.diff(&latest);
+            if !diff.is_empty() {
+                for handler in self.change_handlers.iter() {
+                    handler(&diff);
+                }
+            }
+        }
+        self.last_snapshot = Some(latest);
+    }
+
+    /// Records all future observations as if they happened at the given time
+    /// rather than when they were polled.
+    pub fn set_reference(&mut self, reference: Option<Instant>) {
+        self.reference = reference;
+    }
+
+    /// Forgets all hardware seen on every port while keeping the config.
+    pub fn clear_visibility(&mut self) {
+        for device in self.devices.values_mut() {
+            for port in device.ports.values_mut() {
+                port.clear();
+            }
+        }
+    }
+
+    /// Polls every device, carrying on past pollers that fail. Only
+    /// problems with the config stop the poll.
+    pub fn poll(&mut self) -> Result<PollReport, Error> {
+        self.poll_warnings.clear();
+        self.read_leases();
+
+        let mut report = PollReport::default();
+        for index in 0..self.config.devices.len() {
+            if self.config.devices[index].is_enabled() {
+                self.poll_index(index, &mut report)?;
+            }
+        }
+
+        if self.config.options.reverse_dns {
+            self.resolve_names();
+        }
+
+        self.warn_loops();
+        self.notify_changes();
+
+        Ok(report)
+    }
+
+    /// Polls all devices like `poll` without blocking on files and commands,
+    /// giving up on any poller that takes too long. Other pollers have no
+    /// asynchronous equivalent and block as usual.
+    #[cfg(feature = "async")]
+    pub async fn poll_async(&mut self) -> Result<PollReport, Error> {
+        self.poll_warnings.clear();
+        self.read_leases();
+
+        let mut report = PollReport::default();
+        for index in 0..self.config.devices.len() {
+            let device_config = &self.config.devices[index];
+            if !device_config.is_enabled() || device_config.mac.is_empty() {
+                continue;
+            }
+
+            let polled = self.gather_async(index).await;
+            let failures = self.record(index, polled)?;
+            report.add(&self.config.devices[index].id, failures);
+        }
+
+        if self.config.options.reverse_dns {
+            self.resolve_names();
+        }
+
+        self.warn_loops();
+        self.notify_changes();
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "async")]
+    async fn gather_async(&self, index: usize) -> Polled {
+        let default_timeout = self.config.options.poller_timeout_secs.unwrap_or(10);
+        let timeout = |secs: Option<u64>| Duration::from_secs(secs.unwrap_or(default_timeout));
+        let device_config = &self.config.devices[index];
+        let context = self.context(device_config);
+
+        let mut polled = Polled::default();
+        for port_config in device_config.ports.iter() {
+            for poller in port_config.pollers.iter() {
+                let description = format!("a poller of {} {}", device_config.id, port_config.id);
+                let timeout = timeout(poller.timeout_secs());
+                if let Some(result) =
+                    nonblocking::within(timeout, &description, poller.poll_async(&context)).await
+                {
+                    polled
+                        .ports
+                        .push((port_config.key().to_owned(), poller.source(), result));
+                }
+            }
+        }
+        for poller in device_config.pollers.iter() {
+            let description = format!("a poller of {}", device_config.id);
+            let timeout = timeout(poller.timeout_secs());
+            if let Some(result) =
+                nonblocking::within(timeout, &description, poller.poll_async(&context)).await
+            {
+                polled.devices.push((poller.source(), result));
+            }
+        }
+        polled
+    }
+
+    /// Looks up the hostnames of the addresses of hardware that isn't a known
+    /// device.
+    fn resolve_names(&mut self) {
+        for (mac, addresses) in self.ip_map.iter() {
+            if self.devices.contains_key(mac) {
+                continue;
+            }
+
+            for address in addresses {
+                self.dns_names
+                    .entry(*address)
+                    .or_insert_with(|| dns::reverse_lookup(*address));
+            }
+        }
+    }
+
+    /// The name of hardware that isn't a known device, from its DHCP lease or
+    /// a reverse lookup of its addresses.
+    fn hostname(&self, mac: &MacAddress) -> Option<&str> {
+        self.lease_names.get(mac).map(|n| n.as_str()).or_else(|| {
+            self.addresses(mac)
+                .iter()
+                .find_map(|a| self.dns_names.get(a).and_then(|n| n.as_deref()))
+        })
+    }
+
+    /// Reloads the hostnames and addresses handed out by DHCP. Lease files
+    /// that can't be read are only warned about.
+    fn read_leases(&mut self) {
+        self.lease_names.clear();
+
+        for file in self.config.leases.iter() {
+            let data = match fs::read_to_string(self.root.join(file)) {
+                Ok(data) => data,
+                Err(e) => {
+                    let warning = format!("leases {} could not be read: {}", file, e);
+                    log::warn!("{}", warning);
+                    self.poll_warnings.push(warning);
+                    continue;
+                }
+            };
+
+            for lease in parsers::parse_leases(&data, SystemTime::now()) {
+                let known = self.ip_map.entry(lease.mac).or_default();
+                if !known.contains(&lease.address) {
+                    known.push(lease.address);
+                }
+                if let Some(hostname) = lease.hostname {
+                    self.lease_names.insert(lease.mac, hostname);
+                }
+            }
+        }
+    }
+
+    /// Polls a single device, leaving the rest of the network untouched.
+    pub fn poll_device(&mut self, id: &str) -> Result<PollReport, Error> {
+        let index = self
+            .config
+            .devices
+            .iter()
+            .position(|c| c.id == id && c.is_enabled())
+            .ok_or_else(|| Error::UnknownDevice(id.to_owned()))?;
+
+        let mut report = PollReport::default();
+        self.poll_index(index, &mut report)?;
+        if self.config.options.reverse_dns {
+            self.resolve_names();
+        }
+
+        Ok(report)
+    }
+
+    fn poll_index(&mut self, index: usize, report: &mut PollReport) -> Result<(), Error> {
+        if self.config.devices[index].mac.is_empty() {
+            return Ok(());
+        }
+
+        let polled = self.gather(index);
+        let failures = self.record(index, polled)?;
+        report.add(&self.config.devices[index].id, failures);
+        Ok(())
+    }
+
+    /// The context a device's pollers run in.
+    fn context<'a>(&'a self, device_config: &'a DeviceConfig) -> Context<'a> {
+        Context {
+            root: device_config.root.as_deref().unwrap_or(&self.root),
+            formats: &self.formats,
+            reference: self.reference,
+            offsets: &self.offsets,
+            stdin_read: &self.stdin_read,
+            default_expiry: self.config.default_expiry(),
+        }
+    }
+
+    /// Runs each of a device's pollers without recording what they found.
+    fn gather(&self, index: usize) -> Polled {
+        let device_config = &self.config.devices[index];
+        let context = self.context(device_config);
+
+        let mut polled = Polled::default();
+        for port_config in device_config.ports.iter() {
+            for poller in port_config.pollers.iter() {
+                polled.ports.push((
+                    port_config.key().to_owned(),
+                    poller.source(),
+                    poller.poll(&context),
+                ));
+            }
+        }
+        for poller in device_config.pollers.iter() {
+            polled
+                .devices
+                .push((poller.source(), poller.poll(&context)));
+        }
+        polled
+    }
+
+    /// Records what a device's pollers found.
+    fn record(&mut self, index: usize, polled: Polled) -> Result<Vec<Error>, Error> {
+        let window = self.config.options.window;
+        let snapshot = self.config.options.snapshot;
+        let strip_own_macs = self.config.options.strip_own_macs;
+        let preserve_on_empty = self.config.options.preserve_on_empty;
+        let device_config = &self.config.devices[index];
+        if device_config.mac.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Devices are found by id as their hardware may be shared with others.
+        let slot = self
+            .devices
+            .position(|d| d.id == device_config.id)
+            .ok_or_else(|| {
+                Error::ConfigError(format!("no device was created for {}", device_config.id))
+            })?;
+        let device = self.devices.get_at_mut(slot).unwrap();
+        let mut observed: HashMap<String, HashSet<MacAddress>> = HashMap::new();
+
+        // What the pollers found decides which ports expire. Ports whose
+        // pollers failed keep what they could see until a poll succeeds or it
+        // ages out, as do those whose pollers read an empty file, which is
+        // more likely a broken collection than a quiet port, when asked to.
+        let mut retained: HashSet<&str> = HashSet::new();
+        let mut retain_all = false;
+        let mut warnings = Vec::new();
+        for (port_id, _, result) in polled.ports.iter() {
+            match result {
+                Ok(_) => (),
+                Err(Error::EmptyFile(file)) => {
+                    warnings.push(format!("{} read an empty file {}", device_config.id, file));
+                    if preserve_on_empty {
+                        retained.insert(port_id);
+                    }
+                }
+                Err(Error::Timeout(what)) => {
+                    warnings.push(format!("{} timed out {}", device_config.id, what));
+                    retained.insert(port_id);
+                }
+                Err(_) => {
+                    retained.insert(port_id);
+                }
+            }
+        }
+        for (_, result) in polled.devices.iter() {
+            match result {
+                Ok(_) => (),
+                Err(Error::EmptyFile(file)) => {
+                    warnings.push(format!("{} read an empty file {}", device_config.id, file));
+                    retain_all |= preserve_on_empty;
+                }
+                Err(Error::Timeout(what)) => {
+                    warnings.push(format!("{} timed out {}", device_config.id, what));
+                    retain_all = true;
+                }
+                Err(_) => retain_all = true,
+            }
+        }
+
+        for warning in warnings {
+            log::warn!("{}", warning);
+            if !self.poll_warnings.contains(&warning) {
+                self.poll_warnings.push(warning);
+            }
+        }
+
+        if !snapshot && !retain_all {
+            for (port_id, port) in device.ports.iter_mut() {
+                if !retained.contains(port_id.as_str()) {
+                    port.expire();
+                }
+            }
+        }
+
+        // Failures mark the device as down but the rest of the pollers carry
+        // on.
+        let mut failures = Vec::new();
+
+        for (port_id, source, result) in polled.ports {
+            let port = device.ports.get_mut(&port_id).ok_or_else(|| {
+                Error::ConfigError(format!(
+                    "{} polls port {} which it doesn't have",
+                    device_config.id, port_id
+                ))
+            })?;
+            let mut visible = match result {
+                Ok(visible) => visible,
+                Err(Error::EmptyFile(_)) | Err(Error::Timeout(_)) => continue,
+                Err(error) => {
+                    failures.push(error);
+                    continue;
+                }
+            };
+            self.config.filter(&mut visible);
+            observed
+                .entry(port_id.clone())
+                .or_default()
+                .extend(visible.iter());
+            port.observe(visible, &source);
+        }
+
+        let mut learned = Vec::new();
+        for (source, result) in polled.devices {
+            let data = match result {
+                Ok(data) => data,
+                Err(Error::EmptyFile(_)) | Err(Error::Timeout(_)) => continue,
+                Err(error) => {
+                    failures.push(error);
+                    continue;
+                }
+            };
+            let source = data.source.clone().unwrap_or(source);
+            // The forwarding database only ever places hardware on one port
+            // so anything it reports has moved away from the other ports.
+            let authoritative = source == Source::ForwardDb;
+            let reports = data
+                .ports
+                .into_iter()
+                .map(|(port_id, visible)| (port_id, visible, false))
+                .chain(
+                    data.infrastructure
+                        .into_iter()
+                        .map(|(port_id, visible)| (port_id, visible, true)),
+                );
+            let mut vlans = data.vlans;
+            for (port_id, mut visible, infrastructure) in reports {
+                let learned_vlans = vlans.remove(&port_id).unwrap_or_default();
+                let port_id = device.port_key(port_id);
+                if authoritative {
+                    for (other_id, other) in device.ports.iter_mut() {
+                        if *other_id == port_id {
+                            continue;
+                        }
+
+                        // The same hardware may be reached through different
+                        // ports on different VLANs.
+                        for mac in visible.iter() {
+                            if other.learned_vlans.get(mac) != learned_vlans.get(mac) {
+                                continue;
+                            }
+                            other.forget(mac);
+                            if let Some(seen) = observed.get_mut(other_id) {
+                                seen.remove(mac);
+                            }
+                        }
+                    }
+                }
+
+                if device_config.auto_ports && !device.ports.contains_key(&port_id) {
+                    device
+                        .ports
+                        .insert(port_id.clone(), Port::named(port_id.clone()));
+                }
+
+                if let Some(port) = device.ports.get_mut(&port_id) {
+                    self.config.filter(&mut visible);
+                    observed.entry(port_id).or_default().extend(visible.iter());
+                    for mac in visible.iter() {
+                        if infrastructure {
+                            port.infrastructure.insert(*mac);
+                        } else {
+                            port.infrastructure.remove(mac);
+                        }
+                        match learned_vlans.get(mac) {
+                            Some(vlan) => port.learned_vlans.insert(*mac, *vlan),
+                            None => port.learned_vlans.remove(mac),
+                        };
+                    }
+                    port.observe(visible, &source);
+                }
+            }
+            for (port_id, master) in data.masters {
+                if let Some(port) = device.ports.get_mut(&device.port_key(port_id)) {
+                    port.master = Some(master);
+                }
+            }
+            // Neighbours announcing themselves are direct links.
+            for (port_id, neighbours) in data.neighbours {
+                let port_id = device.port_key(port_id);
+                if device_config.auto_ports && !device.ports.contains_key(&port_id) {
+                    device
+                        .ports
+                        .insert(port_id.clone(), Port::named(port_id.clone()));
+                }
+
+                if let Some(port) = device.ports.get_mut(&port_id) {
+                    for mac in neighbours.iter() {
+                        if let Some(remote_port) = data.remote_ports.get(mac) {
+                            port.remote_ports.insert(*mac, remote_port.clone());
+                        }
+                    }
+                    port.neighbours.extend_from(neighbours);
+                }
+            }
+            learned.extend(data.macs);
+
+            for (mac, addresses) in data.addresses {
+                let known = self.ip_map.entry(mac).or_default();
+                for address in addresses {
+                    if !known.contains(&address) {
+                        known.push(address);
+                    }
+                }
+            }
+        }
+
+        if window > 0 {
+            for (port_id, port) in device.ports.iter_mut() {
+                port.record(observed.remove(port_id).unwrap_or_default(), window);
+            }
+        }
+
+        for error in failures.iter() {
+            let warning = format!("{} could not be polled: {}", device_config.id, error);
+            log::warn!("{}", warning);
+            if !self.poll_warnings.contains(&warning) {
+                self.poll_warnings.push(warning);
+            }
+        }
+        device.last_error = failures.last().map(|e| e.to_string());
+
+        for mac in learned {
+            if self.devices.add_key_at(slot, mac) {
+                if let Some(device) = self.devices.get_at_mut(slot) {
+                    if !device.mac.contains(&mac) {
+                        device.mac.push(mac);
+                    }
+                }
+            } else {
+                let warning = format!(
+                    "{} reported hardware {} which belongs to another device",
+                    device_config.id, mac
+                );
+                log::warn!("{}", warning);
+                if !self.poll_warnings.contains(&warning) {
+                    self.poll_warnings.push(warning);
+                }
+            }
+        }
+
+        if strip_own_macs {
+            if let Some(device) = self.devices.get_at_mut(slot) {
+                for port in device.ports.values_mut() {
+                    for mac in device.mac.iter() {
+                        port.forget(mac);
+                    }
+                }
+            }
+        }
+
+        Ok(failures)
+    }
+
+    pub fn poll_with_observer(
+        &mut self,
+        observer: &mut dyn FnMut(TopologyEvent),
+    ) -> Result<PollReport, Error> {
+        let before = self.topology();
+        let report = self.poll()?;
+        let after = self.topology();
+
+        for event in before.diff(&after) {
+            observer(event);
+        }
+
+        Ok(report)
+    }
+
+    /// The links between devices every renderer draws, in a stable order.
+    pub fn links(&self) -> Vec<Link> {
+        self.topology().links
+    }
+
+    fn topology(&self) -> Topology {
+        // Anything seen within the poll window counts as visible.
+        let now = Instant::now();
+        let mut windowed = self.devices.clone();
+        for device in windowed.values_mut() {
+            for port in device.ports.values_mut() {
+                port.apply_window(now);
+            }
+        }
+
+        // First pass cleans up the visible lists to only the most adjacent.
+        let mut devices = windowed.clone();
+        for device in devices.values_mut() {
+            for port in device.ports.values_mut() {
+                let visible = port.visible.clone();
+                for mac in visible.iter() {
+                    if let Some(other) = windowed.get(mac) {
+                        for other_port in other.ports.values() {
+                            if !other_port.can_see(&device.mac) {
+                                for mac in other_port.visible.iter() {
+                                    port.visible.remove(mac);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Unknown hardware still visible on several ports, like both ends of a
+        // trunk, only stays on the port seeing the least, the nearest one.
+        // Hardware may really be reached through different ports on different
+        // VLANs though.
+        let mut nearest: HashMap<(MacAddress, Option<u16>), (usize, &str, &str)> = HashMap::new();
+        for device in devices.values() {
+            for (port_id, port) in device.ports.iter() {
+                for mac in port.visible.iter().filter(|m| !devices.contains_key(m)) {
+                    let candidate = (port.visible.len(), device.id.as_str(), port_id.as_str());
+                    let key = (*mac, port.learned_vlans.get(mac).copied());
+                    let best = nearest.entry(key).or_insert(candidate);
+                    if candidate < *best {
+                        *best = candidate;
+                    }
+                }
+            }
+        }
+        let nearest: HashMap<(MacAddress, Option<u16>), (String, String)> = nearest
+            .into_iter()
+            .map(|(key, (_, device, port))| (key, (device.to_owned(), port.to_owned())))
+            .collect();
+        for device in devices.values_mut() {
+            for (port_id, port) in device.ports.iter_mut() {
+                let learned_vlans = &port.learned_vlans;
+                port.visible.retain(|mac| {
+                    match nearest.get(&(*mac, learned_vlans.get(mac).copied())) {
+                        Some((id, port)) => *id == device.id && port == port_id,
+                        None => true,
+                    }
+                });
+            }
+        }
+
+        // Then pair up the devices that can see each other. Rather than
+        // comparing every pair only look at the devices owning visible MACs.
+        let by_id: HashMap<&str, &Device> = devices.values().map(|d| (d.id.as_str(), d)).collect();
+        let mut candidates: BTreeSet<(&str, &str)> = BTreeSet::new();
+        for device in devices.values() {
+            for port in device.ports.values() {
+                // Shared hardware like a virtual gateway MAC belongs to
+                // every device declaring it.
+                for mac in port.visible.iter() {
+                    for other in devices.get_all(mac) {
+                        if other.id < device.id {
+                            candidates.insert((&other.id, &device.id));
+                        } else if other.id > device.id {
+                            candidates.insert((&device.id, &other.id));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Neighbours that announced themselves are linked directly, taking
+        // priority over anything inferred between the same pair.
+        let mut announced: BTreeMap<(String, String), Link> = BTreeMap::new();
+        for device in devices.values() {
+            for (port_id, port) in device.ports.iter() {
+                for mac in port.neighbours.iter() {
+                    let other = match devices.get(mac) {
+                        Some(other) if other.id != device.id => other,
+                        _ => continue,
+                    };
+                    let remote_port = port
+                        .remote_ports
+                        .get(mac)
+                        .map(|p| other.port_key(p.clone()))
+                        .filter(|p| other.ports.contains_key(p));
+
+                    let link = Link {
+                        left: device.id.clone(),
+                        left_port: Some(port_id.clone()),
+                        right: other.id.clone(),
+                        right_port: remote_port,
+                        source: Some(Source::Lldp),
+                    }
+                    .normalized();
+
+                    // Both ends may announce the link, fill in what either knows.
+                    let existing = announced
+                        .entry((link.left.clone(), link.right.clone()))
+                        .or_insert_with(|| link.clone());
+                    existing.left_port = existing.left_port.take().or(link.left_port);
+                    existing.right_port = existing.right_port.take().or(link.right_port);
+                }
+            }
+        }
+
+        let mut links: Vec<Link> = candidates
+            .into_iter()
+            .filter(|(left, right)| {
+                !announced.contains_key(&((*left).to_owned(), (*right).to_owned()))
+            })
+            .filter_map(|(left, right)| Link::between(by_id[left], by_id[right]))
+            .collect();
+        links.extend(announced.into_values());
+        links.sort_by(|a, b| (&a.left, &a.right).cmp(&(&b.left, &b.right)));
+
+        Topology { devices, links }
+    }
+
+    /// The device ids in a stable order along with a matrix counting the
+    /// hardware each linked pair of devices can see of each other.
+    pub fn adjacency_matrix(&self) -> (Vec<String>, Vec<Vec<u32>>) {
+        let topology = self.topology();
+
+        let mut labels: Vec<String> = topology.devices.values().map(|d| d.id.clone()).collect();
+        labels.sort();
+        let index: HashMap<&str, usize> = labels
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.as_str(), i))
+            .collect();
+        let by_id: HashMap<&str, &Device> = topology
+            .devices
+            .values()
+            .map(|d| (d.id.as_str(), d))
+            .collect();
+
+        let mut matrix = vec![vec![0; labels.len()]; labels.len()];
+        for link in topology.links.iter() {
+            let left = by_id[link.left.as_str()];
+            let right = by_id[link.right.as_str()];
+
+            let shared: HashSet<&MacAddress> = left
+                .mac
+                .iter()
+                .filter(|m| right.ports.values().any(|p| p.visible.contains(m)))
+                .chain(
+                    right
+                        .mac
+                        .iter()
+                        .filter(|m| left.ports.values().any(|p| p.visible.contains(m))),
+                )
+                .collect();
+
+            let (l, r) = (index[link.left.as_str()], index[link.right.as_str()]);
+            matrix[l][r] = shared.len() as u32;
+            matrix[r][l] = shared.len() as u32;
+        }
+
+        (labels, matrix)
+    }
+
+    /// Lists the loops through the linked devices, each as the ids of the
+    /// devices around it. A loop usually means spanning tree isn't doing its
+    /// job.
+    pub fn detect_loops(&self) -> Vec<Vec<String>> {
+        self.topology().loops()
+    }
+
+    fn warn_loops(&mut self) {
+        for devices in self.detect_loops() {
+            let warning = format!("devices {} form a loop", devices.join(", "));
+            log::warn!("{}.", warning);
+            if !self.poll_warnings.contains(&warning) {
+                self.poll_warnings.push(warning);
+            }
+        }
+    }
+
+    /// Finds the shortest chain of linked devices between two devices.
+    pub fn path_between(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let topology = self.topology();
+
+        let mut neighbours: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for device in topology.devices.values() {
+            neighbours.entry(&device.id).or_default();
+        }
+        for link in topology.links.iter() {
+            neighbours
+                .entry(&link.left)
+                .or_default()
+                .insert(&link.right);
+            neighbours
+                .entry(&link.right)
+                .or_default()
+                .insert(&link.left);
+        }
+
+        if !neighbours.contains_key(from) || !neighbours.contains_key(to) {
+            return None;
+        }
+
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![current.to_owned()];
+                let mut step = current;
+                while let Some(prior) = previous.get(step) {
+                    path.push((*prior).to_owned());
+                    step = prior;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for next in neighbours[current].iter() {
+                if *next != from && !previous.contains_key(next) {
+                    previous.insert(next, current);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Finds the device and port some hardware is plugged into. Hardware
+    /// belonging to a device finds that device without a port, otherwise the
+    /// port seeing the least other hardware is the nearest.
+    pub fn locate(&self, mac: &MacAddress) -> Option<(String, Option<String>)> {
+        let topology = self.topology();
+        if let Some(device) = topology.devices.get(mac) {
+            return Some((device.id.clone(), None));
+        }
+
+        let mut devices: Vec<&Device> = topology.devices.values().collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        devices
+            .into_iter()
+            .flat_map(|device| {
+                sorted_ports(device)
+                    .into_iter()
+                    .filter(|(_, port)| port.visible.contains(mac))
+                    .map(move |(id, port)| (device, id, port.visible.len()))
+            })
+            .min_by_key(|(_, _, visible)| *visible)
+            .map(|(device, id, _)| (device.id.clone(), Some(id.clone())))
+    }
+
+    /// The bridge a device's port is a member of, as the forwarding database
+    /// last reported it.
+    pub fn bridge_master(&self, device: &str, port: &str) -> Option<&str> {
+        self.devices
+            .values()
+            .find(|d| d.id == device)
+            .and_then(|d| d.ports.get(&d.port_key(port.to_owned())))
+            .and_then(|p| p.master.as_deref())
+    }
+
+    pub fn expected(&self) -> &ExpectedTopology {
+        &self.config.expected
+    }
+
+    pub fn verify(&self, expected: &ExpectedTopology) -> Vec<Discrepancy> {
+        verify::verify(expected, &self.links())
+    }
+
+    pub fn state(&self) -> NetworkState {
+        let devices = self
+            .devices
+            .values()
+            .map(|device| {
+                let ports = device
+                    .ports
+                    .iter()
+                    .map(|(id, port)| (id.clone(), port.visible.iter().copied().collect()))
+                    .collect();
+                (device.id.clone(), ports)
+            })
+            .collect();
+
+        NetworkState { devices }
+    }
+
+    /// Captures the discovered links and the hardware nearest to each port so
+    /// they can be compared against a later snapshot.
+    pub fn snapshot(&self) -> Snapshot {
+        let topology = self.topology();
+        let ports = topology
+            .devices
+            .values()
+            .map(|device| {
+                let ports = device
+                    .ports
+                    .iter()
+                    .filter(|(_, port)| !port.visible.is_empty())
+                    .map(|(id, port)| (id.clone(), port.visible.iter().copied().collect()))
+                    .collect();
+                (device.id.clone(), ports)
+            })
+            .collect();
+
+        Snapshot {
+            devices: topology.present_devices().into_iter().collect(),
+            links: topology.links,
+            ports,
+        }
+    }
+
+    /// Writes what each port can see to a file for `load_state` to restore.
+    pub fn save_state(&self, path: &Path) -> Result<(), Error> {
+        let devices = self
+            .devices
+            .values()
+            .map(|device| {
+                let ports = device
+                    .ports
+                    .iter()
+                    .map(|(id, port)| (id.clone(), port.visible.to_persisted()))
+                    .collect();
+                (device.id.clone(), ports)
+            })
+            .collect();
+
+        let data = serde_json::to_string(&SavedState { devices }).map_err(Error::ParseError)?;
+        fs::write(path, data).map_err(Error::IoError)
+    }
+
+    /// Restores what each port could see from a file written by `save_state`,
+    /// ignoring anything that has expired since and devices no longer
+    /// configured.
+    pub fn load_state(&mut self, path: &Path) -> Result<(), Error> {
+        let file = File::open(path).map_err(Error::IoError)?;
+        let state: SavedState =
+            serde_json::from_reader(BufReader::new(file)).map_err(Error::ParseError)?;
+
+        for device in self.devices.values_mut() {
+            let ports = match state.devices.get(&device.id) {
+                Some(ports) => ports,
+                None => continue,
+            };
+            let auto_ports = self
+                .config
+                .devices
+                .iter()
+                .any(|c| c.id == device.id && c.auto_ports);
+
+            for (port_id, items) in ports {
+                if auto_ports && !device.ports.contains_key(port_id) {
+                    device
+                        .ports
+                        .insert(port_id.clone(), Port::named(port_id.clone()));
+                }
+                if let Some(port) = device.ports.get_mut(port_id) {
+                    port.visible
+                        .extend_from(ExpireSet::from_persisted(items.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the hardware visible in the prior state that is no longer
+    /// visible anywhere, along with the device and port it was last seen on.
+    pub fn departures_since(&self, prior: &NetworkState) -> Vec<(MacAddress, String, String)> {
+        let current: HashSet<MacAddress> = self
+            .devices
+            .values()
+            .flat_map(|d| d.ports.values())
+            .flat_map(|p| p.visible.iter().copied())
+            .collect();
+
+        let mut departures = Vec::new();
+        for (device, ports) in prior.devices.iter() {
+            for (port, macs) in ports.iter() {
+                for mac in macs.iter().filter(|m| !current.contains(m)) {
+                    departures.push((*mac, device.clone(), port.clone()));
+                }
+            }
+        }
+
+        departures
+    }
+
+    pub fn unknown_summary(&self) -> UnknownSummary {
+        UnknownSummary::from_topology(&self.topology())
+    }
+
+    /// Counts the unknown hardware across the network by vendor.
+    pub fn vendor_breakdown(&self) -> HashMap<String, usize> {
+        let mut breakdown = HashMap::new();
+        for mac in UnknownSummary::from_topology(&self.topology()).macs.iter() {
+            let vendor = oui::vendor(mac, &self.config.vendors)
+                .unwrap_or_else(|| "Unknown vendor".to_owned());
+            *breakdown.entry(vendor).or_default() += 1;
+        }
+        breakdown
+    }
+
+    pub fn summary(&self) -> Summary {
+        let topology = self.topology();
+
+        Summary {
+            devices: topology.devices.iter().count(),
+            links: topology.links.len(),
+            unknown: UnknownSummary::from_topology(&topology).count,
+            warnings: self.warnings().map(|w| w.to_owned()).collect(),
+        }
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &str> {
+        self.config_warnings
+            .iter()
+            .chain(self.poll_warnings.iter())
+            .map(|w| w.as_str())
+    }
+
+    /// Gauges describing the discovered topology in the Prometheus text
+    /// format, counting the hardware each port is nearest to like the map.
+    pub fn metrics(&self) -> String {
+        let topology = self.topology();
+        let mut devices: Vec<&Device> = topology.devices.values().collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut output = String::new();
+        output.push_str("# HELP netmap_devices Devices known to the network.\n");
+        output.push_str("# TYPE netmap_devices gauge\n");
+        output.push_str(&format!("netmap_devices {}\n", devices.len()));
+        output.push_str("# HELP netmap_links Links discovered between devices.\n");
+        output.push_str("# TYPE netmap_links gauge\n");
+        output.push_str(&format!("netmap_links {}\n", topology.links.len()));
+
+        let mut visible = String::new();
+        let mut unknown = String::new();
+        for device in devices.iter() {
+            for (id, port) in sorted_ports(device) {
+                let labels = format!(
+                    "device=\"{}\",port=\"{}\"",
+                    escape_label(&device.id),
+                    escape_label(id)
+                );
+                let unknown_count = port
+                    .visible
+                    .iter()
+                    .filter(|m| {
+                        !topology.devices.contains_key(m) && !port.infrastructure.contains(m)
+                    })
+                    .count();
+                visible.push_str(&format!(
+                    "netmap_visible_macs{{{}}} {}\n",
+                    labels,
+                    port.visible.len()
+                ));
+                unknown.push_str(&format!(
+                    "netmap_unknown_macs{{{}}} {}\n",
+                    labels, unknown_count
+                ));
+            }
+        }
+
+        output.push_str("# HELP netmap_visible_macs Hardware visible on each port.\n");
+        output.push_str("# TYPE netmap_visible_macs gauge\n");
+        output.push_str(&visible);
+        output.push_str(
+            "# HELP netmap_unknown_macs Hardware visible on each port that isn't a known device.\n",
+        );
+        output.push_str("# TYPE netmap_unknown_macs gauge\n");
+        output.push_str(&unknown);
+        output
+    }
+
+    /// The discovered topology as JSON.
+    pub fn map_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_json()).unwrap()
+    }
+
+    /// The discovered topology as a JSON value, for consuming it
+    /// programmatically.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.topology_json(self.config.options.include_macs)
+    }
+
+    /// Describes the topology, listing each port's hardware either as plain
+    /// addresses or along with how long until each expires.
+    fn topology_json(&self, include_expiry: bool) -> serde_json::Value {
+        let topology = self.topology();
+        let now = Instant::now();
+
+        let devices: Vec<serde_json::Value> = topology
+            .devices
+            .values()
+            .map(|device| {
+                let ports: Vec<serde_json::Value> = device
+                    .ports
+                    .iter()
+                    .map(|(id, port)| {
+                        let macs: Vec<serde_json::Value> = if include_expiry {
+                            port.visible
+                                .iter_with_expiry()
+                                .map(|(mac, expiry)| {
+                                    serde_json::json!({
+                                        "mac": mac,
+                                        "remaining_secs": expiry
+                                            .saturating_duration_since(now)
+                                            .as_secs_f64(),
+                                    })
+                                })
+                                .collect()
+                        } else {
+                            port.visible
+                                .iter()
+                                .map(|mac| serde_json::json!(mac))
+                                .collect()
+                        };
+
+                        let unknown = port
+                            .visible
+                            .iter()
+                            .filter(|m| {
+                                !topology.devices.contains_key(m)
+                                    && !port.infrastructure.contains(m)
+                            })
+                            .count();
+
+                        serde_json::json!({
+                            "id": id,
+                            "name": port.name,
+                            "macs": macs,
+                            "unknown": unknown,
+                        })
+                    })
+                    .collect();
+
+                serde_json::json!({
+                    "id": device.id,
+                    "name": device.name(),
+                    "type": device.device_type.name(),
+                    "mac": device.mac,
+                    "addresses": self.addresses_of(device),
+                    "ports": ports,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "devices": devices,
+            "links": topology.links,
+        })
+    }
+
+    pub fn map_html(&self) -> Result<String, Error> {
+        let svg = render::render(&self.dot(true, None, &|_| true)?, "svg")?;
+        let svg = String::from_utf8_lossy(&svg);
+
+        // Drop the XML prologue so the SVG can be inlined.
+        let svg = match svg.find("<svg") {
+            Some(start) => &svg[start..],
+            None => &svg,
+        };
+
+        Ok(html::page(svg, &self.topology_json(false).to_string()))
+    }
+
+    pub fn map(&self) -> Result<String, Error> {
+        if self.config.options.delta {
+            self.delta_dot()
+        } else {
+            self.dot(false, None, &|_| true)
+        }
+    }
+
+    /// Renders the map with only the devices the predicate accepts, the
+    /// hardware of the rest joins the unknown devices on each port.
+    pub fn map_filtered<F>(&self, keep: F) -> Result<String, Error>
+    where
+        F: Fn(&Device) -> bool,
+    {
+        self.dot(false, None, &keep)
+    }
+
+    /// Renders the map with only the devices of the given types.
+    pub fn map_by_type(&self, types: &[DeviceType]) -> Result<String, Error> {
+        self.map_filtered(|device| types.contains(&device.device_type))
+    }
+
+    /// Renders the map but stops drawing once the deadline passes, labelling
+    /// the graph as truncated.
+    pub fn map_with_deadline(&self, deadline: Instant) -> Result<String, Error> {
+        self.dot(false, Some(deadline), &|_| true)
+    }
+
+    /// Renders only what changed since the last delta render, additions in
+    /// green and removals as red ghosts.
+    fn delta_dot(&self) -> Result<String, Error> {
+        let topology = self.topology();
+        let empty = Topology {
+            devices: MultiMap::default(),
+            links: Vec::new(),
+        };
+        let events = {
+            let rendered = self.rendered.borrow();
+            rendered.as_ref().unwrap_or(&empty).diff(&topology)
+        };
+
+        let label_template = &self.config.options.device_label_template;
+        let by_id: HashMap<&str, &Device> =
+            self.devices.values().map(|d| (d.id.as_str(), d)).collect();
+
+        let mut output = Vec::new();
+        {
+            let mut writer = DotWriter::from(&mut output);
+            let mut graph = writer.graph();
+            apply_graph_options(&mut graph, &self.config.options.graph);
+            if let Some(label) = &self.config.options.graph.label {
+                graph.graph_attributes().set("label", label, true);
+            }
+            let mut nodes: BTreeMap<String, NodeId> = BTreeMap::new();
+
+            let mut node_for = |graph: &mut Scope, id: &str, color: Option<&str>| {
+                if let Some(node) = nodes.get(id) {
+                    return node.clone();
+                }
+
+                let mut node = graph.node_auto();
+                match by_id.get(id) {
+                    Some(device) => {
+                        node.set_label(&device.label(label_template, &self.addresses_of(device)))
+                    }
+                    None => node.set_label(id),
+                };
+                if let Some(color) = color {
+                    mark_change(&mut node, color);
+                }
+                nodes.insert(id.to_owned(), node.id());
+                node.id()
+            };
+
+            // Devices first so those that changed get their colour even when
+            // also part of a changed link.
+            let (device_events, link_events): (Vec<_>, Vec<_>) = events.iter().partition(|e| {
+                matches!(
+                    e,
+                    TopologyEvent::DeviceAppeared(_) | TopologyEvent::DeviceDisappeared(_)
+                )
+            });
+
+            for event in device_events.into_iter().chain(link_events) {
+                match event {
+                    TopologyEvent::DeviceAppeared(id) => {
+                        node_for(&mut graph, id, Some("green"));
+                    }
+                    TopologyEvent::DeviceDisappeared(id) => {
+                        node_for(&mut graph, id, Some("red"));
+                    }
+                    TopologyEvent::LinkAdded(link) | TopologyEvent::LinkRemoved(link) => {
+                        let left = node_for(&mut graph, &link.left, None);
+                        let right = node_for(&mut graph, &link.right, None);
+                        let edge = graph.edge(left, right);
+                        let mut attributes = edge.attributes();
+                        if let TopologyEvent::LinkAdded(_) = event {
+                            attributes.set("color", "green", false);
+                        } else {
+                            attributes.set("color", "red", false);
+                            attributes.set("style", "dashed", false);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.rendered.replace(Some(topology));
+        String::from_utf8(output).map_err(|e| Error::RenderError(e.to_string()))
+    }
+
+    /// Describes an aggregate node, naming whatever hardware the resolver
+    /// knows, listing the addresses of what neighbour tables know and counting
+    /// the rest by vendor.
+    fn aggregate_lines(&self, unknown: &[&MacAddress]) -> (Vec<String>, usize) {
+        let mut lines: Vec<String> = Vec::new();
+        let mut device_count = 0;
+        let mut vendors: BTreeMap<String, usize> = BTreeMap::new();
+        for mac in unknown {
+            match self.name_resolver.as_ref().and_then(|r| r(mac)) {
+                Some(name) => lines.push(name),
+                None if !self.labelled_addresses(mac).is_empty() => {
+                    let addresses: Vec<String> = self
+                        .labelled_addresses(mac)
+                        .iter()
+                        .map(|a| a.to_string())
+                        .collect();
+                    lines.push(addresses.join(", "));
+                }
+                None => {
+                    device_count += 1;
+                    *vendors
+                        .entry(oui::describe(mac, &self.config.vendors))
+                        .or_default() += 1;
+                }
+            }
+        }
+        if device_count > 0 {
+            let mut vendors: Vec<(String, usize)> = vendors.into_iter().collect();
+            vendors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            let breakdown: Vec<String> = vendors
+                .iter()
+                .map(|(vendor, count)| format!("{}× {}", count, vendor))
+                .collect();
+            lines.push(breakdown.join(", "));
+        }
+        (lines, device_count)
+    }
+
+    /// Lays out the map for the renderers other than DOT, clustering devices
+    /// with their ports the same way.
+    fn layout(&self) -> Layout {
+        let topology = self.topology();
+        let devices = &topology.devices;
+        let cluster_min_ports = self.config.options.cluster_min_ports.max(1);
+        let label_template = &self.config.options.device_label_template;
+        let port_counts = self.config.options.port_counts;
+
+        let mut layout = Layout::default();
+        let mut device_nodes: HashMap<&str, usize> = HashMap::new();
+        let mut port_nodes: HashMap<(&str, &str), usize> = HashMap::new();
+
+        let mut ordered: Vec<&Device> = devices.values().collect();
+        ordered.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut emitted: HashSet<&str> = HashSet::new();
+        ordered.retain(|device| emitted.insert(&device.id));
+
+        for device in ordered.iter().copied() {
+            let label = device
+                .label(label_template, &self.addresses_of(device))
+                .replace("\\n", "\n");
+            let ports: Vec<(&String, &Port)> = sorted_ports(device)
+                .into_iter()
+                .filter(|(_id, p)| !p.visible.is_empty())
+                .collect();
+
+            if ports.len() >= cluster_min_ports {
+                let group = layout.groups.len();
+                layout.groups.push(label.clone());
+                let device_node = layout.add_node(label, NodeKind::Device, Some(group));
+                device_nodes.insert(&device.id, device_node);
+
+                for (port_id, port) in ports {
+                    let port_node =
+                        layout.add_node(port.label(port_counts), NodeKind::Port, Some(group));
+                    layout.edges.push((device_node, port_node));
+                    port_nodes.insert((&device.id, port_id), port_node);
+                }
+            } else {
+                let device_node = layout.add_node(label, NodeKind::Device, None);
+                device_nodes.insert(&device.id, device_node);
+            }
+        }
+
+        let node_for = |device: &str, port: Option<&String>| {
+            port.and_then(|port| port_nodes.get(&(device, port.as_str())))
+                .or_else(|| device_nodes.get(device))
+                .copied()
+        };
+
+        for link in topology.links.iter() {
+            let left = node_for(&link.left, link.left_port.as_ref());
+            let right = node_for(&link.right, link.right_port.as_ref());
+            if let (Some(left), Some(right)) = (left, right) {
+                layout.edges.push((left, right));
+            }
+        }
+
+        for device in ordered.iter().copied() {
+            for (port_id, port) in sorted_ports(device) {
+                let port_node = match node_for(&device.id, Some(port_id)) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let mut unknown: Vec<&MacAddress> = port
+                    .visible
+                    .iter()
+                    .filter(|m| !devices.contains_key(m) && !port.infrastructure.contains(m))
+                    .collect();
+                if unknown.is_empty() {
+                    continue;
+                }
+                unknown.sort();
+
+                let (named, unknown): (Vec<&MacAddress>, Vec<&MacAddress>) = unknown
+                    .into_iter()
+                    .partition(|mac| self.hostname(mac).is_some());
+                for mac in named {
+                    let name = self.hostname(mac).unwrap_or_default().to_owned();
+                    let client = layout.add_node(name, NodeKind::Client, None);
+                    layout.edges.push((port_node, client));
+                }
+
+                if !unknown.is_empty() {
+                    let (lines, _) = self.aggregate_lines(&unknown);
+                    let aggregate = layout.add_node(lines.join("\n"), NodeKind::Aggregate, None);
+                    layout.edges.push((port_node, aggregate));
+                }
+            }
+        }
+
+        layout
+    }
+
+    /// Renders the map as GraphML, for editors such as yEd.
+    pub fn map_graphml(&self) -> String {
+        graphml::render(&self.layout())
+    }
+
+    /// Renders the map as a Mermaid flowchart, for embedding in Markdown.
+    pub fn map_mermaid(&self) -> String {
+        mermaid::render(&self.layout())
+    }
+
+    /// Renders the map as DOT, optionally annotating nodes with the ids and
+    /// classes the HTML output relies on. Past the deadline the remaining
+    /// nodes and edges are left out.
+    fn dot(
+        &self,
+        annotate: bool,
+        deadline: Option<Instant>,
+        keep: &dyn Fn(&Device) -> bool,
+    ) -> Result<String, Error> {
+        let mut topology = self.topology();
+        // Filter after the cleanup so hardware behind excluded devices is
+        // still attributed to the nearest port.
+        topology.devices.retain(|device| keep(device));
+        let kept: HashSet<&str> = topology.devices.values().map(|d| d.id.as_str()).collect();
+        topology
+            .links
+            .retain(|link| kept.contains(link.left.as_str()) && kept.contains(link.right.as_str()));
+
+        self.topology_dot(&topology, annotate, deadline)
+    }
+
+    fn topology_dot(
+        &self,
+        topology: &Topology,
+        annotate: bool,
+        deadline: Option<Instant>,
+    ) -> Result<String, Error> {
+        // Links to ports that aren't drawn fall back to the device's node below.
+        if !topology.is_consistent() {
+            log::warn!("topology references ports with no visible hardware.");
+        }
+        let devices = &topology.devices;
+
+        let mut looped: HashSet<(&str, &str)> = HashSet::new();
+        let loops = topology.loops();
+        for devices in loops.iter() {
+            for (i, left) in devices.iter().enumerate() {
+                let right = &devices[(i + 1) % devices.len()];
+                looped.insert((left, right));
+                looped.insert((right, left));
+            }
+        }
+
+        let mut output = Vec::new();
+        {
+            let mut writer = DotWriter::from(&mut output);
+            let mut graph = writer.graph();
+            apply_graph_options(&mut graph, &self.config.options.graph);
+            let mut truncated = false;
+            let mut out_of_time = || {
+                truncated |= deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                truncated
+            };
+
+            let mut device_nodes: HashMap<String, NodeId> = HashMap::new();
+            let mut port_nodes: HashMap<(String, String), NodeId> = HashMap::new();
+
+            // Now generate all the device nodes.
+            let cluster_min_ports = self.config.options.cluster_min_ports.max(1);
+            let label_template = &self.config.options.device_label_template;
+            let port_counts = self.config.options.port_counts;
+            let color_by_vlan = self.config.options.color_by_vlan;
+            let show_down = self.config.options.show_down;
+            let mark_asymmetric = self.config.options.mark_asymmetric;
+            let scale_aggregates = self.config.options.scale_aggregates;
+            let aggregate_per_device = self.config.options.aggregate_per_device;
+            let show_ages = self.config.options.show_ages;
+            let edge_ports = self.config.options.graph.edge_ports;
+            let now = Instant::now();
+
+            // Draw devices in a stable order and each only once even when it
+            // is reachable through several of its hardware addresses.
+            let mut ordered: Vec<&Device> = devices.values().collect();
+            ordered.sort_by(|a, b| a.id.cmp(&b.id));
+            let mut emitted: HashSet<String> = HashSet::new();
+            ordered.retain(|device| emitted.insert(device.id.clone()));
+
+            // In compact mode ports leading to a single neighbour aren't drawn,
+            // the device connects directly instead.
+            let compact = self.config.options.compact;
+            let mut port_links: HashMap<(&str, &str), usize> = HashMap::new();
+            for link in topology.links.iter() {
+                if let Some(port) = &link.left_port {
+                    *port_links.entry((&link.left, port)).or_default() += 1;
+                }
+                if let Some(port) = &link.right_port {
+                    *port_links.entry((&link.right, port)).or_default() += 1;
+                }
+            }
+            for device in ordered.iter().filter(|_| !aggregate_per_device) {
+                for (id, port) in device.ports.iter() {
+                    if port
+                        .visible
+                        .iter()
+                        .any(|m| !devices.contains_key(m) && !port.infrastructure.contains(m))
+                    {
+                        *port_links.entry((&device.id, id)).or_default() += 1;
+                    }
+                }
+            }
+
+            for device in ordered.iter().copied() {
+                if out_of_time() {
+                    break;
+                }
+
+                let is_elided = |port_id: &String| {
+                    compact
+                        && port_links
+                            .get(&(device.id.as_str(), port_id.as_str()))
+                            .copied()
+                            .unwrap_or(0)
+                            <= 1
+                };
+                let visible_ports = device
+                    .ports
+                    .iter()
+                    .filter(|(id, p)| !p.visible.is_empty() && !is_elided(id))
+                    .count();
+
+                if visible_ports >= cluster_min_ports && self.config.options.record_nodes {
+                    let mut ports: Vec<(&String, &Port)> = device
+                        .ports
+                        .iter()
+                        .filter(|(_id, p)| !p.visible.is_empty())
+                        .collect();
+                    ports.sort_by(|a, b| a.0.cmp(b.0));
+
+                    let fields: Vec<String> = ports
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (_id, port))| {
+                            format!("<p{}> {}", i, escape_record(&port.label(port_counts)))
+                        })
+                        .collect();
+
+                    let mut node = graph.node_auto();
+                    node.set_label(&format!(
+                        "{{{}|{{{}}}}}",
+                        escape_record(&device.label(label_template, &self.addresses_of(device))),
+                        fields.join("|")
+                    ));
+                    node.set("shape", "record", false);
+                    fill_device(&mut node, &device.device_type);
+                    if annotate {
+                        annotate_device(&mut node, device);
+                    }
+                    if show_down && device.last_error.is_some() {
+                        mark_down(&mut node);
+                    }
+
+                    let node_id = node.id();
+                    for (i, (port_id, _port)) in ports.iter().enumerate() {
+                        let port_node = String::from(node_id.port(&format!("p{}", i)));
+                        port_nodes
+                            .insert((device.id.clone(), (*port_id).clone()), port_node.into());
+                    }
+                    device_nodes.insert(device.id.clone(), node_id);
+                } else if visible_ports >= cluster_min_ports {
+                    let mut cluster = graph.cluster();
+                    let device_id = {
+                        let mut node = cluster.node_auto();
+                        node.set_label(&device.label(label_template, &self.addresses_of(device)));
+                        style_device(&mut node, &device.device_type);
+                        if annotate {
+                            annotate_device(&mut node, device);
+                        }
+                        if show_down && device.last_error.is_some() {
+                            mark_down(&mut node);
+                        }
+                        device_nodes.insert(device.id.clone(), node.id());
+                        node.id()
+                    };
+
+                    for (port_id, port) in sorted_ports(device) {
+                        if !port.visible.is_empty() && !is_elided(port_id) {
+                            let port_node_id = {
+                                let mut node = cluster.node_auto();
+                                node.set_label(&port.label(port_counts));
+                                // Points hide their label so SSIDs are drawn
+                                // as small boxes instead.
+                                if port.ssid.is_some() {
+                                    node.set("shape", "box", false);
+                                    node.set("style", "rounded", false);
+                                } else {
+                                    node.set("shape", "point", false);
+                                }
+                                if let Some(color) = port.vlan_color().filter(|_| color_by_vlan) {
+                                    node.set("color", color, false);
+                                }
+                                if annotate {
+                                    node.set(
+                                        "id",
+                                        &format!("port-{}-{}", device.id, port_id),
+                                        true,
+                                    );
+                                    node.set(
+                                        "class",
+                                        &format!("type-{}", device.device_type.name()),
+                                        true,
+                                    );
+                                }
+                                port_nodes.insert((device.id.clone(), port_id.clone()), node.id());
+                                node.id()
+                            };
+                            cluster.edge(device_id.clone(), port_node_id);
+                        }
+                    }
+                } else {
+                    let mut node = graph.node_auto();
+                    node.set_label(&device.label(label_template, &self.addresses_of(device)));
+                    style_device(&mut node, &device.device_type);
+                    if annotate {
+                        annotate_device(&mut node, device);
+                    }
+                    if show_down && device.last_error.is_some() {
+                        mark_down(&mut node);
+                    }
+                    device_nodes.insert(device.id.clone(), node.id());
+                }
+            }
+
+            // Ports of devices that weren't clustered attach to the device itself.
+            let node_for = |device: &str, port: Option<&String>| {
+                port.and_then(|port| port_nodes.get(&(device.to_owned(), port.clone())))
+                    .or_else(|| device_nodes.get(device))
+            };
+
+            let port_name = |device: &str, port: Option<&String>| {
+                let port = port?;
+                devices
+                    .values()
+                    .find(|d| d.id == device)
+                    .and_then(|d| d.ports.get(port))
+                    .map(|p| p.name.clone())
+                    .or_else(|| Some(port.clone()))
+            };
+
+            // Final pass lists all the connections
+            for link in topology.links.iter() {
+                if out_of_time() {
+                    break;
+                }
+
+                let left_node = node_for(&link.left, link.left_port.as_ref());
+                let right_node = node_for(&link.right, link.right_port.as_ref());
+
+                match (left_node, right_node) {
+                    (Some(left_node), Some(right_node)) => {
+                        let edge = graph.edge(left_node, right_node);
+                        let style = if mark_asymmetric && link.is_asymmetric() {
+                            Some("dashed")
+                        } else {
+                            link.source.as_ref().and_then(|s| s.style())
+                        };
+                        let mut attributes = edge.attributes();
+                        if let Some(style) = style {
+                            attributes.set("style", style, false);
+                        }
+                        if looped.contains(&(link.left.as_str(), link.right.as_str())) {
+                            attributes.set("color", "red", false);
+                        }
+
+                        if edge_ports != EdgePorts::Hidden {
+                            let left_port = port_name(&link.left, link.left_port.as_ref());
+                            let right_port = port_name(&link.right, link.right_port.as_ref());
+                            let end = |device: &str, port: &Option<String>| match port {
+                                Some(port) => format!("{} {}", device, port),
+                                None => device.to_owned(),
+                            };
+                            attributes.set(
+                                "tooltip",
+                                &format!(
+                                    "{} to {}",
+                                    end(&link.left, &left_port),
+                                    end(&link.right, &right_port)
+                                ),
+                                true,
+                            );
+
+                            if edge_ports == EdgePorts::Labels {
+                                if let Some(port) = &left_port {
+                                    attributes.set("taillabel", port, true);
+                                }
+                                if let Some(port) = &right_port {
+                                    attributes.set("headlabel", port, true);
+                                }
+                            }
+                        }
+                    }
+                    _ => log::warn!(
+                        "skipping link between {} and {}, no node was rendered.",
+                        link.left,
+                        link.right
+                    ),
+                }
+            }
+
+            // How long ago the stalest of the hardware was seen and how much
+            // of its expiry is left.
+            let freshness = |ports: &[&Port], macs: &[&MacAddress]| {
+                macs.iter()
+                    .filter_map(|mac| {
+                        ports
+                            .iter()
+                            .filter_map(|port| {
+                                Some((*port.last_seen.get(mac)?, port.visible.get_expiry(mac)?))
+                            })
+                            .max_by_key(|(seen, _)| *seen)
+                    })
+                    .min_by_key(|(seen, _)| *seen)
+                    .map(|(seen, expiry)| {
+                        let lifetime = expiry.saturating_duration_since(seen).as_secs_f64();
+                        let remaining = expiry.saturating_duration_since(now).as_secs_f64();
+                        let left = if lifetime > 0.0 {
+                            (remaining / lifetime).min(1.0)
+                        } else {
+                            0.0
+                        };
+                        (now.saturating_duration_since(seen), left)
+                    })
+            };
+
+            let aggregate =
+                |graph: &mut Scope, unknown: Vec<&MacAddress>, age: Option<Duration>| {
+                    let (mut lines, device_count) = self.aggregate_lines(&unknown);
+                    if let Some(age) = age {
+                        lines.push(format!("oldest seen {}s ago", age.as_secs()));
+                    }
+
+                    let mut other_node = graph.node_auto();
+                    other_node.set_label(&lines.join("\\n"));
+                    if scale_aggregates {
+                        let scale = aggregate_scale(device_count);
+                        other_node.set("width", &format!("{:.2}", 0.75 * scale), false);
+                        other_node.set(
+                            "fontsize",
+                            &format!("{:.1}", 14.0 * (1.0 + scale) / 2.0),
+                            false,
+                        );
+                    }
+                    other_node.id()
+                };
+
+            // Named hardware gets a node of its own, the rest is aggregated.
+            let attach = |graph: &mut Scope,
+                          from: &NodeId,
+                          ports: &[&Port],
+                          unknown: Vec<&MacAddress>,
+                          label: Option<&str>| {
+                let (named, unknown): (Vec<&MacAddress>, Vec<&MacAddress>) = unknown
+                    .into_iter()
+                    .partition(|mac| self.hostname(mac).is_some());
+
+                for mac in named {
+                    let named_node_id = {
+                        let mut node = graph.node_auto();
+                        node.set_label(self.hostname(mac).unwrap_or_default());
+                        node.id()
+                    };
+                    let edge = graph.edge(from, named_node_id);
+                    let mut attributes = edge.attributes();
+                    if let Some(label) = label {
+                        attributes.set_label(label);
+                    }
+                    if let Some((_, left)) = freshness(ports, &[mac]).filter(|_| show_ages) {
+                        attributes.set("color", &age_color(left), true);
+                    }
+                }
+
+                if !unknown.is_empty() {
+                    let fresh = freshness(ports, &unknown).filter(|_| show_ages);
+                    let other_node_id = aggregate(graph, unknown, fresh.map(|(age, _)| age));
+                    let edge = graph.edge(from, other_node_id);
+                    let mut attributes = edge.attributes();
+                    if let Some(label) = label {
+                        attributes.set_label(label);
+                    }
+                    if let Some((_, left)) = fresh {
+                        attributes.set("color", &age_color(left), true);
+                    }
+                }
+            };
+
+            for device in ordered.iter().copied() {
+                if out_of_time() {
+                    break;
+                }
+
+                if aggregate_per_device {
+                    let unknown: BTreeSet<&MacAddress> = device
+                        .ports
+                        .values()
+                        .flat_map(|port| {
+                            port.visible.iter().filter(|m| {
+                                !devices.contains_key(m) && !port.infrastructure.contains(m)
+                            })
+                        })
+                        .collect();
+
+                    if unknown.is_empty() {
+                        continue;
+                    }
+
+                    if let Some(device_node) = device_nodes.get(&device.id) {
+                        let ports: Vec<&Port> = device.ports.values().collect();
+                        attach(
+                            &mut graph,
+                            device_node,
+                            &ports,
+                            unknown.into_iter().collect(),
+                            None,
+                        );
+                    }
+                    continue;
+                }
+
+                for (id, port) in sorted_ports(device) {
+                    if port.visible.is_empty() {
+                        continue;
+                    }
+
+                    let port_node = match node_for(&device.id, Some(id)) {
+                        Some(node) => node,
+                        None => {
+                            log::warn!(
+                                "skipping port {} of {}, no node was rendered.",
+                                id,
+                                device.id
+                            );
+                            continue;
+                        }
+                    };
+                    let mut unknown: Vec<&MacAddress> = port
+                        .visible
+                        .iter()
+                        .filter(|m| !devices.contains_key(m) && !port.infrastructure.contains(m))
+                        .collect();
+
+                    if unknown.is_empty() {
+                        continue;
+                    }
+
+                    unknown.sort();
+
+                    // Ports carrying several VLANs get an edge for each.
+                    let mut by_vlan: BTreeMap<Option<u16>, Vec<&MacAddress>> = BTreeMap::new();
+                    for mac in unknown {
+                        by_vlan
+                            .entry(port.learned_vlans.get(mac).copied())
+                            .or_default()
+                            .push(mac);
+                    }
+                    let split = by_vlan.len() > 1;
+                    for (vlan, unknown) in by_vlan {
+                        let label = vlan.filter(|_| split).map(|vlan| format!("VLAN {}", vlan));
+                        attach(&mut graph, port_node, &[port], unknown, label.as_deref());
+                    }
+                }
+            }
+
+            if self.config.options.legend {
+                let mut legend = graph.cluster();
+                legend.set_label("Legend");
+                for device_type in DeviceType::ALL.iter() {
+                    let mut node = legend.node_auto();
+                    node.set_label(device_type.name());
+                    style_device(&mut node, device_type);
+                }
+            }
+
+            let label = match (&self.config.options.graph.label, truncated) {
+                (Some(label), true) => Some(format!("{} (truncated)", label)),
+                (Some(label), false) => Some(label.clone()),
+                (None, true) => Some("truncated".to_owned()),
+                (None, false) => None,
+            };
+            if let Some(label) = label {
+                graph.graph_attributes().set("label", &label, true);
+            }
+        }
+
+        String::from_utf8(output).map_err(|e| Error::RenderError(e.to_string()))
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn escape_label(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escapes the characters that have special meaning in record labels.
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Grows with the number of devices an aggregate node stands for, starting at
+/// 1 for a single device.
+fn aggregate_scale(count: usize) -> f64 {
+    1.0 + (count.max(1) as f64).log10()
+}
+
+fn sorted_ports(device: &Device) -> Vec<(&String, &Port)> {
+    let mut ports: Vec<(&String, &Port)> = device.ports.iter().collect();
+    ports.sort_by(|a, b| a.0.cmp(b.0));
+    ports
+}
+
+/// Draws a device in the shape and colour of its type.
+fn style_device(node: &mut Node, device_type: &DeviceType) {
+    node.set("shape", device_type.shape(), false);
+    if let DeviceType::Router = device_type {
+        node.set("peripheries", "2", false);
+    }
+    fill_device(node, device_type);
+}
+
+fn fill_device(node: &mut Node, device_type: &DeviceType) {
+    node.set("style", "filled", false);
+    node.set("fillcolor", device_type.fill_color(), false);
+}
+
+fn apply_graph_options(graph: &mut Scope, options: &GraphOptions) {
+    let attributes: Vec<(&str, String, bool)> = [
+        ("rankdir", options.rankdir.clone(), false),
+        ("ranksep", options.ranksep.map(|r| r.to_string()), false),
+        ("nodesep", options.nodesep.map(|n| n.to_string()), false),
+        ("splines", options.splines.clone(), false),
+        ("fontname", options.fontname.clone(), true),
+    ]
+    .into_iter()
+    .filter_map(|(name, value, quote)| value.map(|value| (name, value, quote)))
+    .collect();
+
+    // An empty attribute list would still be written out.
+    if !attributes.is_empty() {
+        let mut graph_attributes = graph.graph_attributes();
+        for (name, value, quote) in attributes.iter() {
+            graph_attributes.set(name, value, *quote);
+        }
+    }
+
+    if let Some(fontname) = &options.fontname {
+        graph.node_attributes().set("fontname", fontname, true);
+    }
+}
+
+fn is_link_local(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V6(address) => address.segments()[0] & 0xffc0 == 0xfe80,
+        IpAddr::V4(_) => false,
+    }
+}
+
+/// A colour from red for hardware about to expire to green for hardware just
+/// seen, as a Graphviz HSV colour.
+fn age_color(left: f64) -> String {
+    format!("{:.3} 1.000 0.800", left / 3.0)
+}
+
+/// Greys out a device that couldn't be polled.
+fn mark_down(node: &mut Node) {
+    node.set("style", "dashed", false);
+    node.set("color", "gray", false);
+    node.set("fontcolor", "gray", false);
+}
+
+/// Colours a device that appeared or disappeared, disappearing devices are
+/// drawn as ghosts.
+fn mark_change(node: &mut Node, color: &str) {
+    node.set("color", color, false);
+    if color == "red" {
+        node.set("style", "dashed", false);
+    }
+}
+
+fn annotate_device(node: &mut Node, device: &Device) {
+    node.set("id", &format!("device-{}", device.id), true);
+    node.set(
+        "class",
+        &format!("type-{}", device.device_type.name()),
+        true,
+    );
+}
 
 impl TryFrom<&Path> for Network {
     type Error = Error;
 
     fn try_from(config_file: &Path) -> Result<Self, Self::Error> {
-        let file = File::open(config_file).map_err(Error::IoError)?;
-        let reader = BufReader::new(file);
+        let config = NetworkConfig::load(config_file)?;
+        config.validate()?;
+        Ok(Network::new(config, config_file.parent().unwrap()))
+    }
+}
 
-        let config: NetworkConfig = serde_json::from_reader(reader).map_err(Error::ParseError)?;
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
 
-        Ok(Network::new(config, config_file.parent().unwrap()))
+    use super::*;
+
+    fn mac(mac: &str) -> MacAddress {
+        MacAddress::parse_str(mac).unwrap()
+    }
+
+    /// A fresh directory for a test's poller files.
+    fn root(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("netmap-{}-{}", name, process::id()))
+    }
+
+    fn write(name: &str, file: &str, contents: &str) {
+        fs::write(root(name).join(file), contents).unwrap();
+    }
+
+    /// Builds a network from a JSON config reading its pollers' files from
+    /// the test's directory.
+    fn network(name: &str, config: &str, files: &[(&str, &str)]) -> Network {
+        let root = root(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (file, contents) in files {
+            write(name, file, contents);
+        }
+
+        let config: NetworkConfig = serde_json::from_str(config).unwrap();
+        Network::new(config, &root)
+    }
+
+    #[test]
+    fn shared_first_mac_records_on_the_polled_device() {
+        let mut network = network(
+            "shared_first_mac",
+            r#"{
+                "shared_macs": ["00:11:22:33:44:aa"],
+                "devices": [
+                    {"id": "gw1", "mac": ["00:11:22:33:44:aa", "00:11:22:33:44:01"], "ports": [{"id": "lan"}]},
+                    {"id": "gw2", "mac": ["00:11:22:33:44:aa", "00:11:22:33:44:02"], "ports": [{"id": "lan"}],
+                     "pollers": [{"type": "file", "file": "gw2.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:03"]}
+                ]
+            }"#,
+            &[("gw2.fdb", "00:11:22:33:44:03 dev lan\n")],
+        );
+        assert_eq!(network.warnings().count(), 0);
+
+        network.poll().unwrap();
+        let links = network.links();
+        assert_eq!(links.len(), 1);
+        assert!(links[0].left == "gw2" || links[0].right == "gw2");
+        assert!(links[0].left != "gw1" && links[0].right != "gw1");
+    }
+
+    #[test]
+    fn links_use_the_nearest_port() {
+        let (sw, r, other) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:09"),
+        );
+        let network = NetworkBuilder::new()
+            .device("sw", &[sw], &["a", "b", "c"])
+            .device("r", &[r], &[])
+            .visible("sw", "a", &[r, other])
+            .visible("sw", "b", &[r])
+            .visible("sw", "c", &[r, other])
+            .build()
+            .unwrap();
+
+        for _ in 0..10 {
+            let links = network.links();
+            assert_eq!(links.len(), 1);
+            let port = if links[0].left == "sw" {
+                &links[0].left_port
+            } else {
+                &links[0].right_port
+            };
+            assert_eq!(port.as_deref(), Some("b"));
+        }
+    }
+
+    #[test]
+    fn links_to_empty_ports_render() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let network = NetworkBuilder::new()
+            .device("sw", &[sw], &["a"])
+            .device("r", &[r], &["x"])
+            .visible("sw", "a", &[r])
+            .build()
+            .unwrap();
+
+        // Pruning can leave a link to a port that no longer sees anything.
+        let mut topology = network.topology();
+        assert_eq!(topology.links.len(), 1);
+        let link = &mut topology.links[0];
+        if link.left == "r" {
+            link.left_port = Some("x".to_owned());
+        } else {
+            link.right_port = Some("x".to_owned());
+        }
+        assert!(!topology.is_consistent());
+
+        let dot = network.topology_dot(&topology, false, None).unwrap();
+        // The link falls back to r's device node as its port was never drawn.
+        assert!(dot.contains("node_0 [label=\"r\", shape=ellipse"));
+        assert!(dot.contains("node_3 [label=\"a\", shape=point];"));
+        assert!(dot.contains("node_0 -- node_3 [tooltip=\"r x to sw a\"];"));
+        assert!(!dot.contains("label=\"x\""));
+    }
+
+    #[test]
+    fn owner_index_finds_the_same_links_as_all_pairs() {
+        let macs: Vec<MacAddress> = (1..=6)
+            .map(|i| mac(&format!("00:11:22:33:44:{:02x}", i)))
+            .collect();
+        let unknown = mac("00:11:22:33:44:99");
+        let network = NetworkBuilder::new()
+            .device("sw1", &[macs[0]], &["a", "b", "c"])
+            .device("sw2", &[macs[1]], &["a", "b"])
+            .device("ap", &[macs[2]], &["lan"])
+            .device("r1", &[macs[3]], &[])
+            .device("r2", &[macs[4]], &[])
+            .device("r3", &[macs[5]], &[])
+            .visible("sw1", "a", &[macs[3]])
+            .visible("sw1", "b", &[macs[1], macs[4], macs[2], macs[5]])
+            .visible("sw1", "c", &[unknown])
+            .visible("sw2", "a", &[macs[0], macs[3]])
+            .visible("sw2", "b", &[macs[4], macs[2], macs[5]])
+            .visible("ap", "lan", &[macs[1], macs[0], macs[3], macs[4]])
+            .build()
+            .unwrap();
+
+        let mut topology = network.topology();
+        let optimized: HashSet<Link> = topology.links.iter().map(|l| l.normalized()).collect();
+
+        let mut naive = HashSet::new();
+        topology.devices.visit_pairs(|left, right| {
+            naive.extend(Link::between(left, right).map(|l| l.normalized()));
+        });
+
+        assert!(naive.len() >= 4);
+        assert_eq!(optimized, naive);
+    }
+
+    #[test]
+    fn fdb_self_entries_are_learned_as_own_hardware() {
+        let mut network = network(
+            "fdb_self_entries",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "learn_self_macs": true}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:09 dev lan1 self\n00:11:22:33:44:02 dev lan1\n",
+            )],
+        );
+        network.poll().unwrap();
+
+        let known = network.known_macs();
+        let (_, macs) = known.iter().find(|(id, _)| id == "sw").unwrap();
+        assert_eq!(
+            macs,
+            &vec![mac("00:11:22:33:44:01"), mac("00:11:22:33:44:09")]
+        );
+        assert_eq!(
+            network.locate(&mac("00:11:22:33:44:09")),
+            Some(("sw".to_owned(), None))
+        );
+    }
+
+    #[test]
+    fn custom_formats_are_polled() {
+        let mut network = network(
+            "custom_formats",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.txt", "format": {"custom": "pairs"}}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[("sw.txt", "lan1=00:11:22:33:44:02\n")],
+        );
+        network.register_device_format("pairs", |data| {
+            let mut ports: HashMap<String, ExpireSet<MacAddress>> = HashMap::new();
+            for (port, address) in data.lines().filter_map(|l| l.split_once('=')) {
+                ports
+                    .entry(port.to_owned())
+                    .or_default()
+                    .insert(mac(address), Instant::now() + Duration::from_secs(60));
+            }
+            Ok(ports)
+        });
+
+        network.poll().unwrap();
+        let links = network.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            (links[0].left.as_str(), links[0].left_port.as_deref()),
+            ("r", None)
+        );
+        assert_eq!(
+            (links[0].right.as_str(), links[0].right_port.as_deref()),
+            ("sw", Some("lan1"))
+        );
+    }
+
+    #[test]
+    fn hardware_declared_twice_is_reported() {
+        let network = network(
+            "hardware_declared_twice",
+            r#"{
+                "devices": [
+                    {"id": "a", "mac": ["00:11:22:33:44:01"]},
+                    {"id": "b", "mac": ["00:11:22:33:44:02", "00:11:22:33:44:01"]}
+                ]
+            }"#,
+            &[],
+        );
+
+        let warnings: Vec<&str> = network.warnings().collect();
+        assert_eq!(
+            warnings,
+            vec!["hardware 00:11:22:33:44:01 is declared by both a and b"]
+        );
+    }
+
+    #[test]
+    fn window_remembers_recent_polls() {
+        let mut network = network(
+            "window",
+            r#"{
+                "options": {"window": 3},
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:02 dev lan1\n")],
+        );
+
+        network.poll().unwrap();
+        assert_eq!(network.links().len(), 1);
+
+        // The router only shows up intermittently.
+        write("window", "sw.fdb", "00:11:22:33:44:09 dev lan1\n");
+        for _ in 0..2 {
+            network.poll().unwrap();
+            assert_eq!(network.links().len(), 1);
+        }
+
+        network.poll().unwrap();
+        assert_eq!(network.links().len(), 0);
+    }
+
+    #[test]
+    fn dumped_config_round_trips() {
+        let network = network(
+            "dumped_config",
+            r#"{
+                "options": {"window": 2, "strip_own_macs": true},
+                "devices": [
+                    {"id": "sw", "name": "Switch", "mac": ["00:11:22:33:44:01"],
+                     "ports": [{"id": "lan1", "name": "Uplink"}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 30}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[],
+        );
+
+        let dumped: NetworkConfig = serde_json::from_str(&network.dump_config()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&dumped).unwrap(),
+            serde_json::to_value(&network.config).unwrap()
+        );
+        assert_eq!(dumped.devices.len(), 2);
+        assert_eq!(dumped.devices[0].ports[0].name.as_deref(), Some("Uplink"));
+    }
+
+    #[test]
+    fn links_to_unrendered_nodes_are_skipped() {
+        let network = NetworkBuilder::new()
+            .device("sw", &[mac("00:11:22:33:44:01")], &["a"])
+            .build()
+            .unwrap();
+
+        let mut topology = network.topology();
+        topology.links.push(Link {
+            left: "ghost".to_owned(),
+            left_port: Some("missing".to_owned()),
+            right: "sw".to_owned(),
+            right_port: Some("a".to_owned()),
+            source: None,
+        });
+
+        let dot = network.topology_dot(&topology, false, None).unwrap();
+        assert!(!dot.contains("->") && !dot.contains(" -- "));
+    }
+
+    #[test]
+    fn snapshot_mode_keeps_everything() {
+        let mut network = network(
+            "snapshot_mode",
+            r#"{
+                "options": {"snapshot": true},
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]},
+                    {"id": "ap", "mac": ["00:11:22:33:44:03"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:02 dev lan1\n")],
+        );
+        network.poll().unwrap();
+
+        write("snapshot_mode", "sw.fdb", "00:11:22:33:44:03 dev lan2\n");
+        network.poll().unwrap();
+        write("snapshot_mode", "sw.fdb", "00:11:22:33:44:09 dev lan2\n");
+        network.poll().unwrap();
+
+        let links: Vec<(String, String)> = network
+            .links()
+            .into_iter()
+            .map(|l| (l.left, l.right))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("ap".to_owned(), "sw".to_owned()),
+                ("r".to_owned(), "sw".to_owned())
+            ]
+        );
+        assert_eq!(network.unknown_summary().count, 1);
+
+        network.clear_visibility();
+        assert!(network.links().is_empty());
+        assert!(network
+            .devices
+            .values()
+            .all(|d| d.ports.values().all(|p| p.last_seen.is_empty())));
+    }
+
+    #[test]
+    fn new_links_are_observed() {
+        let mut network = network(
+            "new_links",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:09 dev lan1\n")],
+        );
+
+        let mut events = Vec::new();
+        network
+            .poll_with_observer(&mut |event| events.push(event))
+            .unwrap();
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, TopologyEvent::LinkAdded(_))));
+
+        write("new_links", "sw.fdb", "00:11:22:33:44:02 dev lan1\n");
+        events.clear();
+        network
+            .poll_with_observer(&mut |event| events.push(event))
+            .unwrap();
+        let added: Vec<&Link> = events
+            .iter()
+            .filter_map(|e| match e {
+                TopologyEvent::LinkAdded(link) => Some(link),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(added.len(), 1);
+        assert_eq!(
+            (added[0].left.as_str(), added[0].right.as_str()),
+            ("r", "sw")
+        );
+    }
+
+    #[test]
+    fn own_hardware_is_stripped_from_ports() {
+        let mut network = network(
+            "own_hardware",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:01 dev lan1\n00:11:22:33:44:02 dev lan1\n",
+            )],
+        );
+        let sw = mac("00:11:22:33:44:01");
+        let sees_itself = |network: &Network| {
+            network.devices.get(&sw).unwrap().ports["lan1"]
+                .visible
+                .contains(&sw)
+        };
+
+        network.poll().unwrap();
+        assert!(sees_itself(&network));
+
+        network.clear_visibility();
+        network.options_mut().strip_own_macs = true;
+        network.poll().unwrap();
+        assert!(!sees_itself(&network));
+        assert_eq!(network.links().len(), 1);
+    }
+
+    #[test]
+    fn devices_with_few_ports_are_plain_nodes() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("r", &[r], &[])
+            .visible("sw", "lan1", &[r])
+            .build()
+            .unwrap();
+        assert!(network.map().unwrap().contains("subgraph cluster"));
+
+        network.options_mut().cluster_min_ports = 2;
+        let map = network.map().unwrap();
+        assert!(!map.contains("subgraph cluster"));
+        assert!(map.contains("\"sw\""));
+    }
+
+    #[test]
+    fn record_nodes_anchor_links_on_ports() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("r", &[r], &[])
+            .visible("sw", "lan1", &[r])
+            .visible("sw", "lan2", &[mac("00:11:22:33:44:09")])
+            .build()
+            .unwrap();
+        network.options_mut().record_nodes = true;
+
+        let map = network.map().unwrap();
+        assert!(map.contains(r#"[label="{sw|{<p0> lan1|<p1> lan2}}", shape=record"#));
+        assert!(map.contains("node_0 -- node_1:p0"));
+        assert!(map.contains("node_1:p1 -- node_2"));
+    }
+
+    #[test]
+    fn tailed_files_only_parse_new_lines() {
+        let mut network = network(
+            "tailed_files",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "tail": true, "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]},
+                    {"id": "ap", "mac": ["00:11:22:33:44:03"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:02 dev lan1\n")],
+        );
+        let linked = |network: &Network| -> Vec<String> {
+            network.links().into_iter().map(|l| l.left).collect()
+        };
+
+        network.poll().unwrap();
+        assert_eq!(linked(&network), vec!["r"]);
+
+        write(
+            "tailed_files",
+            "sw.fdb",
+            "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:03 dev lan2\n",
+        );
+        network.poll().unwrap();
+        assert_eq!(linked(&network), vec!["ap"]);
+    }
+
+    #[test]
+    fn unknown_hardware_is_counted_once() {
+        let (sw, ap, unknown) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:03"),
+            mac("00:11:22:33:44:99"),
+        );
+        let network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("ap", &[ap], &["lan"])
+            .visible("sw", "lan1", &[unknown])
+            .visible("ap", "lan", &[unknown])
+            .build()
+            .unwrap();
+
+        let summary = network.unknown_summary();
+        assert_eq!(summary.count, 1);
+        assert_eq!(summary.macs, BTreeSet::from([unknown]));
+    }
+
+    #[test]
+    fn links_record_how_they_were_found() {
+        let mut network = network(
+            "link_sources",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}, {"id": "lan2"}],
+                     "pollers": [
+                         {"type": "file", "file": "sw.fdb", "format": "fdb"},
+                         {"type": "file", "file": "sw.lldp", "format": "lldp"}
+                     ]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]},
+                    {"id": "sw2", "mac": ["00:11:22:33:44:03"], "ports": [{"id": "gi1"}]}
+                ]
+            }"#,
+            &[
+                ("sw.fdb", "00:11:22:33:44:02 dev lan1\n"),
+                (
+                    "sw.lldp",
+                    "lldp.lan2.via=LLDP\n\
+                     lldp.lan2.chassis.mac=00:11:22:33:44:03\n\
+                     lldp.lan2.port.ifname=gi1\n",
+                ),
+            ],
+        );
+        network.poll().unwrap();
+
+        let sources: Vec<(String, String, Option<Source>)> = network
+            .links()
+            .into_iter()
+            .map(|l| (l.left, l.right, l.source))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("r".to_owned(), "sw".to_owned(), Some(Source::ForwardDb)),
+                ("sw".to_owned(), "sw2".to_owned(), Some(Source::Lldp)),
+            ]
+        );
+    }
+
+    #[test]
+    fn cleared_networks_have_no_edges() {
+        let mut network = network(
+            "cleared_networks",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:09 dev lan1\n",
+            )],
+        );
+        network.poll().unwrap();
+        assert!(network.map().unwrap().contains(" -- "));
+
+        network.clear_visibility();
+        let map = network.map().unwrap();
+        assert!(!map.contains(" -- "));
+        assert!(map.contains("label=\"sw\""));
+        assert_eq!(network.summary().unknown, 0);
+    }
+
+    #[test]
+    fn adjacency_matrix_is_symmetric() {
+        let (sw, r, ap1, ap2) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:03"),
+            mac("00:11:22:33:44:04"),
+        );
+        let network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("r", &[r], &[])
+            .device("ap", &[ap1, ap2], &[])
+            .visible("sw", "lan1", &[r])
+            .visible("sw", "lan2", &[ap1, ap2])
+            .build()
+            .unwrap();
+
+        let (labels, matrix) = network.adjacency_matrix();
+        assert_eq!(labels, vec!["ap", "r", "sw"]);
+        assert_eq!(matrix, vec![vec![0, 0, 2], vec![0, 0, 1], vec![2, 1, 0]]);
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                assert_eq!(*cell, matrix[j][i]);
+            }
+        }
+    }
+
+    #[test]
+    fn labels_follow_the_template() {
+        let network = network(
+            "label_template",
+            r#"{
+                "options": {"device_label_template": "{name}\n{ip}\n({type}) {mac}"},
+                "devices": [
+                    {"id": "sw", "name": "core-sw1", "type": "Switch", "ip": "10.0.0.1",
+                     "mac": ["00:11:22:33:44:01"]}
+                ]
+            }"#,
+            &[],
+        );
+
+        assert!(network
+            .map()
+            .unwrap()
+            .contains(r#"label="core-sw1\n10.0.0.1\n(switch) 00:11:22:33:44:01""#));
+    }
+
+    #[test]
+    fn departed_hardware_is_reported() {
+        let mut network = network(
+            "departed_hardware",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:31 dev lan2\n",
+            )],
+        );
+        network.poll().unwrap();
+        let prior = network.state();
+
+        write(
+            "departed_hardware",
+            "sw.fdb",
+            "00:11:22:33:44:02 dev lan1\n",
+        );
+        network.poll().unwrap();
+        assert_eq!(
+            network.departures_since(&prior),
+            vec![(mac("00:11:22:33:44:31"), "sw".to_owned(), "lan2".to_owned())]
+        );
+    }
+
+    #[test]
+    fn disabled_devices_are_skipped() {
+        let mut network = network(
+            "disabled_devices",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]},
+                    {"id": "staged", "enabled": false, "mac": ["00:11:22:33:44:03"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "missing.fdb", "format": "fdb"}]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:02 dev lan1\n")],
+        );
+        let report = network.poll().unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(network.warnings().count(), 0);
+        assert_eq!(network.links().len(), 1);
+        let map = network.map().unwrap();
+        assert!(map.contains("label=\"sw\""));
+        assert!(!map.contains("staged"));
+    }
+
+    #[test]
+    fn ports_can_match_ignoring_case() {
+        let config = r#"{
+            "devices": [
+                {"id": "sw", "case_insensitive_ports": CASE, "mac": ["00:11:22:33:44:01"],
+                 "ports": [{"id": "gi1/0/1"}],
+                 "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                {"id": "r", "mac": ["00:11:22:33:44:02"]}
+            ]
+        }"#;
+        let files = [("sw.fdb", "00:11:22:33:44:02 dev Gi1/0/1\n")];
+
+        let mut insensitive = network(
+            "ports_ignoring_case",
+            &config.replace("CASE", "true"),
+            &files,
+        );
+        insensitive.poll().unwrap();
+        let links = insensitive.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].right_port.as_deref(), Some("gi1/0/1"));
+
+        let mut sensitive = network(
+            "ports_matching_case",
+            &config.replace("CASE", "false"),
+            &files,
+        );
+        sensitive.poll().unwrap();
+        assert!(sensitive.links().is_empty());
+    }
+
+    #[test]
+    fn port_labels_count_clients() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("r", &[r], &[])
+            .visible(
+                "sw",
+                "lan1",
+                &[r, mac("00:11:22:33:44:31"), mac("00:11:22:33:44:32")],
+            )
+            .visible("sw", "lan2", &[mac("00:11:22:33:44:33")])
+            .build()
+            .unwrap();
+        network.options_mut().port_counts = true;
+
+        let map = network.map().unwrap();
+        assert!(map.contains("[label=\"lan1 (3)\""));
+        assert!(map.contains("[label=\"lan2 (1)\""));
+    }
+
+    #[test]
+    fn bonded_ports_are_one_link() {
+        let mut network = network(
+            "bonded_ports",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"],
+                     "ports": [{"id": "lan1", "bond": "bond0"}, {"id": "lan2", "bond": "bond0"}, {"id": "lan3"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "sw2", "mac": ["00:11:22:33:44:02", "00:11:22:33:44:05"]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:05 dev lan2\n",
+            )],
+        );
+        network.poll().unwrap();
+
+        let links = network.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            (links[0].left_port.as_deref(), links[0].right.as_str()),
+            (Some("bond0"), "sw2")
+        );
+        let map = network.map().unwrap();
+        assert!(map.contains("node_2 -- node_3"));
+        assert!(!map.contains("lan1") && !map.contains("lan2"));
+    }
+
+    #[test]
+    fn paths_follow_the_links() {
+        let (a, b, c) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:03"),
+        );
+        let network = NetworkBuilder::new()
+            .device("a", &[a], &["p"])
+            .device("b", &[b], &["x", "y"])
+            .device("c", &[c], &["q"])
+            .visible("a", "p", &[b, c])
+            .visible("b", "x", &[a])
+            .visible("b", "y", &[c])
+            .visible("c", "q", &[b, a])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            network.path_between("a", "c"),
+            Some(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()])
+        );
+        assert_eq!(network.path_between("a", "a"), Some(vec!["a".to_owned()]));
+        assert_eq!(network.path_between("a", "missing"), None);
+    }
+
+    #[test]
+    fn networks_give_back_their_config() {
+        let network = network(
+            "into_config",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[],
+        );
+
+        let config = network.into_config();
+        let devices: Vec<(&str, &[MacAddress])> = config
+            .devices
+            .iter()
+            .map(|d| (d.id.as_str(), d.mac.as_slice()))
+            .collect();
+        assert_eq!(
+            devices,
+            vec![
+                ("sw", &[mac("00:11:22:33:44:01")][..]),
+                ("r", &[mac("00:11:22:33:44:02")][..])
+            ]
+        );
+    }
+
+    #[test]
+    fn ports_are_coloured_by_vlan() {
+        let mut network = network(
+            "vlan_colours",
+            r#"{
+                "options": {"color_by_vlan": true},
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"],
+                     "ports": [{"id": "lan1", "vlans": [10]}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]}
+                ]
+            }"#,
+            &[(
+                "sw.fdb",
+                "00:11:22:33:44:31 dev lan1\n00:11:22:33:44:32 dev lan2 vlan 20\n",
+            )],
+        );
+        network.poll().unwrap();
+
+        let map = network.map().unwrap();
+        assert!(map.contains(r#"[label="lan1", shape=point, color=green3]"#));
+        assert!(map.contains(r#"[label="lan2", shape=point, color=purple]"#));
+    }
+
+    #[test]
+    fn failing_devices_are_drawn_down() {
+        let mut network = network(
+            "failing_devices",
+            r#"{
+                "options": {"show_down": true},
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"],
+                     "pollers": [{"type": "file", "file": "missing.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[],
+        );
+        let report = network.poll().unwrap();
+        assert!(!report.is_complete());
+
+        let map = network.map().unwrap();
+        let line = |label: &str| {
+            map.lines()
+                .find(|l| l.contains(&format!("[label=\"{}\"", label)))
+                .unwrap()
+                .to_owned()
+        };
+        assert!(line("sw").contains("style=dashed, color=gray, fontcolor=gray"));
+        assert!(!line("r").contains("dashed"));
+    }
+
+    #[test]
+    fn arp_and_ndp_addresses_are_combined() {
+        let mut network = network(
+            "arp_and_ndp",
+            r#"{
+                "options": {"device_label_template": "{name} {ip}"},
+                "devices": [
+                    {"id": "r", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan"}],
+                     "pollers": [
+                         {"type": "file", "file": "arp", "format": "proc-arp"},
+                         {"type": "file", "file": "neigh", "format": "ip-neigh"}
+                     ]},
+                    {"id": "pc", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[
+                (
+                    "arp",
+                    "IP address       HW type     Flags       HW address            Mask     Device\n\
+                     192.168.1.5      0x1         0x2         00:11:22:33:44:02     *        lan\n",
+                ),
+                (
+                    "neigh",
+                    "2001:db8::5 dev lan lladdr 00:11:22:33:44:02 REACHABLE\n",
+                ),
+            ],
+        );
+        network.poll().unwrap();
+
+        let mut addresses = network.ip_map[&mac("00:11:22:33:44:02")].clone();
+        addresses.sort();
+        assert_eq!(
+            addresses,
+            vec![
+                "192.168.1.5".parse::<IpAddr>().unwrap(),
+                "2001:db8::5".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert!(network
+            .map()
+            .unwrap()
+            .contains("pc 192.168.1.5, 2001:db8::5"));
+    }
+
+    #[test]
+    fn empty_networks_render() {
+        let mut empty = network("empty_network", r#"{"devices": []}"#, &[]);
+        empty.poll().unwrap();
+        assert_eq!(empty.map().unwrap(), "graph {\n}\n");
+        assert!(empty.links().is_empty());
+        assert_eq!(empty.adjacency_matrix(), (Vec::new(), Vec::new()));
+        assert_eq!(empty.summary().devices, 0);
+        assert_eq!(empty.path_between("a", "b"), None);
+
+        let mut single = network(
+            "single_device",
+            r#"{"devices": [{"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}]}]}"#,
+            &[],
+        );
+        single.poll().unwrap();
+        assert!(single.map().unwrap().contains("label=\"sw\""));
+        assert!(single.links().is_empty());
+        assert_eq!(
+            single.adjacency_matrix(),
+            (vec!["sw".to_owned()], vec![vec![0]])
+        );
+    }
+
+    #[test]
+    fn one_sided_links_are_dashed() {
+        let (sw1, sw2, r) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:03"),
+        );
+        let mut network = NetworkBuilder::new()
+            .device("sw1", &[sw1], &["lan1", "lan2"])
+            .device("sw2", &[sw2], &["lan1"])
+            .device("r", &[r], &["wan"])
+            .visible("sw1", "lan1", &[sw2])
+            .visible("sw2", "lan1", &[sw1])
+            .visible("sw1", "lan2", &[r])
+            .build()
+            .unwrap();
+        network.options_mut().mark_asymmetric = true;
+
+        let map = network.map().unwrap();
+        let edge = |tooltip: &str| {
+            map.lines()
+                .find(|l| l.contains(&format!("tooltip=\"{}\"", tooltip)))
+                .unwrap()
+                .to_owned()
+        };
+        assert!(edge("r to sw1 lan2").contains("style=dashed"));
+        assert!(!edge("sw1 lan1 to sw2 lan1").contains("dashed"));
+    }
+
+    #[test]
+    fn devices_are_drawn_once() {
+        let macs: Vec<MacAddress> = (1..=4)
+            .map(|i| mac(&format!("00:11:22:33:44:{:02x}", i)))
+            .collect();
+        // Each network hashes its devices differently, changing the order
+        // they're iterated in.
+        for _ in 0..5 {
+            let network = NetworkBuilder::new()
+                .device("sw", &macs[0..1], &["lan1", "lan2"])
+                .device("ap", &macs[1..4], &["wlan0"])
+                .device("r", &[mac("00:11:22:33:44:05")], &[])
+                .visible("sw", "lan1", &macs[1..4])
+                .visible("sw", "lan2", &[mac("00:11:22:33:44:05")])
+                .visible("ap", "wlan0", &[macs[0], mac("00:11:22:33:44:05")])
+                .build()
+                .unwrap();
+            let map = network.map().unwrap();
+            for id in ["sw", "ap", "r"] {
+                assert_eq!(
+                    map.matches(&format!("[label=\"{}\"", id)).count(),
+                    1,
+                    "{}",
+                    id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn resolved_names_label_unknown_hardware() {
+        let (sw, phone) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:31"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1"])
+            .visible("sw", "lan1", &[phone])
+            .build()
+            .unwrap();
+        network.set_name_resolver(Box::new(move |mac| {
+            (*mac == phone).then(|| "Alice's phone".to_owned())
+        }));
+
+        assert!(network
+            .map()
+            .unwrap()
+            .contains(r#"[label="Alice's phone"]"#));
+    }
+
+    #[test]
+    fn compact_maps_skip_single_link_ports() {
+        let (sw, r, ap1, ap2) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:03"),
+            mac("00:11:22:33:44:04"),
+        );
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("r", &[r], &[])
+            .device("ap1", &[ap1], &[])
+            .device("ap2", &[ap2], &[])
+            .visible("sw", "lan1", &[r])
+            .visible("sw", "lan2", &[ap1, ap2])
+            .build()
+            .unwrap();
+        network.options_mut().compact = true;
+
+        let map = network.map().unwrap();
+        assert!(!map.contains(r#"[label="lan1""#));
+        assert!(map.contains(r#"node_5 [label="lan2", shape=point]"#));
+        assert!(map.contains(r#"node_2 -- node_4 [tooltip="r to sw lan1"]"#));
+        assert!(map.contains(r#"node_0 -- node_5 [tooltip="ap1 to sw lan2"]"#));
+        assert!(map.contains(r#"node_1 -- node_5 [tooltip="ap2 to sw lan2"]"#));
+    }
+
+    #[test]
+    fn json_lists_visible_hardware_with_its_expiry() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1"])
+            .device("r", &[r], &[])
+            .visible_until("sw", "lan1", r, Instant::now() + Duration::from_secs(60))
+            .build()
+            .unwrap();
+        network.options_mut().include_macs = true;
+
+        let json = network.to_json();
+        let sw = json["devices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["id"] == "sw")
+            .unwrap();
+        let macs = sw["ports"][0]["macs"].as_array().unwrap();
+        assert_eq!(macs.len(), 1);
+        assert_eq!(macs[0]["mac"], "00:11:22:33:44:02");
+        let remaining = macs[0]["remaining_secs"].as_f64().unwrap();
+        assert!(remaining > 0.0 && remaining <= 60.0);
+    }
+
+    #[test]
+    fn vlan_sub_interfaces_attach_to_their_port() {
+        let mut network = network(
+            "vlan_sub_interfaces",
+            r#"{
+                "devices": [
+                    {"id": "r", "strip_vlan_suffix": true, "mac": ["00:11:22:33:44:01"],
+                     "ports": [{"id": "eth0"}],
+                     "pollers": [{"type": "file", "file": "r.fdb", "format": "fdb"}]},
+                    {"id": "sw", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[(
+                "r.fdb",
+                "00:11:22:33:44:02 dev eth0.100\n00:11:22:33:44:31 dev eth0.200\n",
+            )],
+        );
+        network.poll().unwrap();
+
+        let links = network.links();
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            (links[0].left.as_str(), links[0].left_port.as_deref()),
+            ("r", Some("eth0"))
+        );
+        assert_eq!(
+            network.locate(&mac("00:11:22:33:44:31")),
+            Some(("r".to_owned(), Some("eth0".to_owned())))
+        );
+    }
+
+    #[test]
+    fn delta_maps_mark_new_links() {
+        let mut network = network(
+            "delta_maps",
+            r#"{
+                "options": {"delta": true},
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}, {"id": "lan2"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]},
+                    {"id": "ap", "mac": ["00:11:22:33:44:03"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:02 dev lan1\n")],
+        );
+        network.poll().unwrap();
+        network.map().unwrap();
+
+        write(
+            "delta_maps",
+            "sw.fdb",
+            "00:11:22:33:44:02 dev lan1\n00:11:22:33:44:03 dev lan2\n",
+        );
+        network.poll().unwrap();
+        let map = network.map().unwrap();
+        assert!(map.contains(r#"node_0 [label="ap", color=green]"#));
+        assert!(map.contains("node_0 -- node_1 [color=green]"));
+        assert!(!map.contains(r#"label="r""#));
+    }
+
+    #[test]
+    fn larger_aggregates_are_drawn_wider() {
+        let unknown = |n: usize| MacAddress::new([0x00, 0x11, 0x22, 0x00, (n >> 8) as u8, n as u8]);
+        let many: Vec<MacAddress> = (0..200).map(unknown).collect();
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[mac("00:11:22:33:44:01")], &["lan1", "lan2"])
+            .visible("sw", "lan1", &many)
+            .visible("sw", "lan2", &[unknown(500), unknown(501)])
+            .build()
+            .unwrap();
+        network.options_mut().scale_aggregates = true;
+
+        let map = network.map().unwrap();
+        let width = |count: &str| -> f64 {
+            let line = map
+                .lines()
+                .find(|l| l.contains(&format!("[label=\"{}× ", count)))
+                .unwrap();
+            let start = line.find("width=").unwrap() + "width=".len();
+            line[start..]
+                .split([',', ']'])
+                .next()
+                .unwrap()
+                .trim_matches('"')
+                .parse()
+                .unwrap()
+        };
+        assert!(width("200") > width("2"));
+    }
+
+    #[test]
+    fn unknown_hardware_is_tallied_by_vendor() {
+        let network = NetworkBuilder::new()
+            .device("sw", &[mac("00:11:22:33:44:01")], &["lan1", "lan2"])
+            .visible(
+                "sw",
+                "lan1",
+                &[
+                    mac("b8:27:eb:00:00:01"),
+                    mac("b8:27:eb:00:00:02"),
+                    mac("dc:a6:32:00:00:03"),
+                ],
+            )
+            .visible(
+                "sw",
+                "lan2",
+                &[mac("00:0c:29:00:00:04"), mac("00:11:22:00:00:05")],
+            )
+            .build()
+            .unwrap();
+
+        let breakdown = network.vendor_breakdown();
+        assert_eq!(
+            breakdown,
+            HashMap::from([
+                ("Raspberry Pi".to_owned(), 3),
+                ("VMware".to_owned(), 1),
+                ("Unknown vendor".to_owned(), 1)
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_clients_can_be_aggregated_per_device() {
+        let (sw, ap) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:03"));
+        let mut network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1", "lan2"])
+            .device("ap", &[ap], &["wlan0"])
+            .visible("sw", "lan1", &[ap, mac("00:11:22:33:44:31")])
+            .visible(
+                "sw",
+                "lan2",
+                &[mac("00:11:22:33:44:32"), mac("00:11:22:33:44:33")],
+            )
+            .visible("ap", "wlan0", &[sw, mac("00:11:22:33:44:34")])
+            .build()
+            .unwrap();
+
+        assert_eq!(network.map().unwrap().matches("× unknown\"]").count(), 3);
+
+        network.options_mut().aggregate_per_device = true;
+        let map = network.map().unwrap();
+        assert_eq!(map.matches("× unknown\"]").count(), 2);
+        assert!(map.contains("node_7 [label=\"1× unknown\"];\n  node_1 -- node_7;"));
+        assert!(map.contains("node_8 [label=\"3× unknown\"];\n  node_4 -- node_8;"));
+    }
+
+    #[test]
+    fn empty_files_warn_and_can_preserve_visibility() {
+        let config = r#"{
+            "devices": [
+                {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                 "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                {"id": "r", "mac": ["00:11:22:33:44:02"]}
+            ]
+        }"#;
+        let client = mac("00:11:22:33:44:09");
+
+        for preserve in [false, true] {
+            let mut network = network(
+                "empty_files",
+                config,
+                &[("sw.fdb", "00:11:22:33:44:09 dev lan1\n")],
+            );
+            network.options_mut().preserve_on_empty = preserve;
+            network.poll().unwrap();
+            assert_eq!(
+                network.locate(&client),
+                Some(("sw".to_owned(), Some("lan1".to_owned())))
+            );
+
+            write("empty_files", "sw.fdb", "");
+            network.poll().unwrap();
+            assert_eq!(network.poll_warnings.len(), 1);
+            assert!(network.poll_warnings[0].starts_with("sw read an empty file"));
+            assert_eq!(network.locate(&client).is_some(), preserve);
+        }
+    }
+
+    #[test]
+    fn failing_polls_keep_visibility_until_it_ages_out() {
+        let mut network = network(
+            "failing_polls",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+            &[("sw.fdb", "00:11:22:33:44:09 dev lan1\n")],
+        );
+        let (old, new) = (mac("00:11:22:33:44:09"), mac("00:11:22:33:44:0a"));
+        let lan1 = Some(("sw".to_owned(), Some("lan1".to_owned())));
+        network.poll().unwrap();
+        assert_eq!(network.locate(&old), lan1);
+
+        // A file missing mid-rotation keeps what the port could see.
+        fs::remove_file(root("failing_polls").join("sw.fdb")).unwrap();
+        network.poll().unwrap();
+        assert_eq!(network.locate(&old), lan1);
+
+        // The next successful poll ages it out as usual.
+        write("failing_polls", "sw.fdb", "00:11:22:33:44:0a dev lan1\n");
+        network.poll().unwrap();
+        assert_eq!(network.locate(&old), None);
+        assert_eq!(network.locate(&new), lan1);
+    }
+
+    #[test]
+    fn ssid_ports_render_as_labelled_nodes() {
+        let mut network = network(
+            "ssid_ports",
+            r#"{
+                "devices": [
+                    {"id": "ap", "mac": ["00:11:22:33:44:01"],
+                     "ports": [{"id": "wlan0", "ssid": "Home"}, {"id": "wlan1", "ssid": "Guest"}],
+                     "pollers": [{"type": "file", "file": "ap.fdb", "format": "fdb"}]}
+                ]
+            }"#,
+            &[(
+                "ap.fdb",
+                "00:11:22:33:44:09 dev wlan0\n00:11:22:33:44:0a dev wlan1\n",
+            )],
+        );
+        network.poll().unwrap();
+        let map = network.map().unwrap();
+        assert!(map
+            .contains("node_2 [label=\"Home\", shape=box, style=rounded];\n    node_1 -- node_2;"));
+        assert!(map.contains(
+            "node_3 [label=\"Guest\", shape=box, style=rounded];\n    node_1 -- node_3;"
+        ));
+    }
+
+    #[test]
+    fn passed_deadlines_render_a_truncated_map() {
+        let (sw, r) = (mac("00:11:22:33:44:01"), mac("00:11:22:33:44:02"));
+        let network = NetworkBuilder::new()
+            .device("sw", &[sw], &["lan1"])
+            .device("r", &[r], &[])
+            .visible("sw", "lan1", &[r])
+            .build()
+            .unwrap();
+
+        let map = network.map_with_deadline(Instant::now()).unwrap();
+        assert_eq!(map, "graph {\n  graph [label=\"truncated\"];\n}\n");
+
+        let map = network
+            .map_with_deadline(Instant::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(map.contains(" -- ") && !map.contains("truncated"));
+    }
+
+    #[test]
+    fn locate_finds_the_nearest_switch_port() {
+        let (sw1, sw2, sw3) = (
+            mac("00:11:22:33:44:01"),
+            mac("00:11:22:33:44:02"),
+            mac("00:11:22:33:44:03"),
+        );
+        let client = mac("00:11:22:33:44:09");
+        let network = NetworkBuilder::new()
+            .device("sw1", &[sw1], &["down"])
+            .device("sw2", &[sw2], &["up", "down"])
+            .device("sw3", &[sw3], &["up", "lan"])
+            .visible("sw1", "down", &[sw2, sw3, client])
+            .visible("sw2", "up", &[sw1])
+            .visible("sw2", "down", &[sw3, client])
+            .visible("sw3", "up", &[sw1, sw2])
+            .visible("sw3", "lan", &[client])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            network.locate(&client),
+            Some(("sw3".to_owned(), Some("lan".to_owned())))
+        );
+        assert_eq!(network.locate(&sw2), Some(("sw2".to_owned(), None)));
+        assert_eq!(network.locate(&mac("00:11:22:33:44:0a")), None);
+    }
+
+    #[test]
+    fn unknown_clouds_seen_on_two_ports_are_counted_once() {
+        let cloud = [
+            mac("00:11:22:33:44:31"),
+            mac("00:11:22:33:44:32"),
+            mac("00:11:22:33:44:33"),
+        ];
+        let network = NetworkBuilder::new()
+            .device("sw", &[mac("00:11:22:33:44:01")], &["lan1", "lan2"])
+            .visible("sw", "lan1", &cloud)
+            .visible("sw", "lan2", &cloud)
+            .build()
+            .unwrap();
+
+        let map = network.map().unwrap();
+        assert_eq!(map.matches("× unknown\"]").count(), 1);
+        assert!(map.contains("[label=\"3× unknown\"]"));
+        assert_eq!(network.summary().unknown, 3);
+    }
+
+    #[test]
+    fn locally_administered_hardware_is_randomized() {
+        let mut network = network(
+            "randomized",
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]}
+                ]
+            }"#,
+            &[("sw.fdb", "02:00:00:00:00:05 dev lan1 master br0\n")],
+        );
+        network.poll().unwrap();
+        assert!(network.map().unwrap().contains("[label=\"1× randomized\"]"));
+
+        network.clear_visibility();
+        network.config.ignore_local_macs = true;
+        network.poll().unwrap();
+        assert!(!network.map().unwrap().contains("randomized"));
     }
 }
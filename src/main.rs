@@ -1,13 +1,818 @@
-use std::{env::current_dir, error::Error, path::PathBuf};
+use std::{
+    env::{self, current_dir},
+    error::Error,
+    fs,
+    io::{self, stderr, stdout, BufRead, BufReader, IsTerminal, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{exit, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use clap::Parser;
-use netmap::Network;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use netmap::{
+    Link, MapOptions, Network, NetworkConfig, Scheduler, Summary, TopologyDiff, TopologyEvent,
+};
+use serde_json::json;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MapFormat {
+    Dot,
+    Svg,
+    Png,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Dot,
+    Graphml,
+    Mermaid,
+}
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Config file to load
-    file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    // Without a subcommand the network is polled as with `poll`.
+    #[command(flatten)]
+    poll: PollArgs,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Poll the network once and print the map
+    Poll(PollArgs),
+    /// Keep polling, serving the map over HTTP or writing it to a file
+    Serve(ServeArgs),
+    /// Poll the network once and write the map in another format
+    Export(ExportArgs),
+    /// Check the config for problems without polling
+    Validate(ValidateArgs),
+}
+
+// How to find the network to map, shared by the subcommands.
+#[derive(Args)]
+struct NetworkArgs {
+    /// Config files to load, the devices of each are merged into one network
+    files: Vec<PathBuf>,
+
+    /// Build the map from a directory of archived dumps, one subdirectory per
+    /// device
+    #[arg(long, value_name = "PATH")]
+    import_dir: Option<PathBuf>,
+
+    /// Keep everything observed instead of expiring it
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Connect devices directly through ports that only have one neighbour
+    #[arg(long)]
+    compact: bool,
+
+    /// Name unknown clients by looking up the hostnames of their addresses
+    #[arg(long)]
+    reverse_dns: bool,
+
+    /// The direction to lay out the map in, like LR or TB
+    #[arg(long, value_name = "DIRECTION")]
+    rankdir: Option<String>,
+
+    /// A title to draw beneath the map
+    #[arg(long)]
+    label: Option<String>,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("sink").args(["output", "listen"])))]
+struct PollArgs {
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    /// When to color the summary written to stderr
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Compare the discovered links against the expected topology instead of
+    /// printing the map
+    #[arg(long)]
+    verify: bool,
+
+    /// Keep polling, spreading the devices across this many seconds, and print
+    /// the map after each round
+    #[arg(long, value_name = "SECONDS")]
+    watch: Option<u64>,
+
+    /// While watching, log each change to the links as a line of JSON instead
+    /// of printing the map
+    #[arg(long, requires = "watch")]
+    log_changes: bool,
+
+    /// Stay running, polling every this many seconds and writing the map to
+    /// the output file, like `serve`
+    #[arg(long, value_name = "SECONDS", requires = "sink")]
+    interval: Option<u64>,
+
+    /// Where to write the map instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// The format to write the map in, anything but dot is rendered by
+    /// Graphviz
+    #[arg(long, value_enum, default_value_t = MapFormat::Dot)]
+    format: MapFormat,
+
+    /// Serve the map over HTTP on this address, polling every interval, like
+    /// `serve`, instead of writing it to the output file
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "state")]
+    listen: Option<SocketAddr>,
+
+    /// When polling on an interval, restore what was visible from this file
+    /// on startup and save it again on shutdown
+    #[arg(long, value_name = "PATH", requires = "interval")]
+    state: Option<PathBuf>,
+
+    /// Exit with a status of 2 if no links between devices and no unknown
+    /// hardware were discovered, even if the map's options hide some of them
+    #[arg(long)]
+    fail_if_empty: bool,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("sink").args(["output", "listen"]).required(true)))]
+struct ServeArgs {
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    /// How often to poll, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    interval: u64,
+
+    /// Serve the map, its JSON and metrics over HTTP on this address
+    #[arg(long, value_name = "ADDRESS")]
+    listen: Option<SocketAddr>,
+
+    /// Write the map to this file after every poll
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// The format to write the map file in, anything but dot is rendered by
+    /// Graphviz
+    #[arg(long, value_enum, default_value_t = MapFormat::Dot, requires = "output")]
+    format: MapFormat,
+
+    /// Restore what was visible from this file on startup and save it again
+    /// on shutdown
+    #[arg(long, value_name = "PATH", requires = "output")]
+    state: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    network: NetworkArgs,
+
+    /// The format to write the map in
+    #[arg(long, value_enum)]
+    format: ExportFormat,
+
+    /// Where to write the map instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// Config files to load, the devices of each are merged into one network
+    files: Vec<PathBuf>,
+}
+
+fn use_color(choice: ColorChoice) -> bool {
+    wants_color(
+        choice,
+        env::var_os("NO_COLOR").is_some(),
+        stderr().is_terminal(),
+    )
+}
+
+fn wants_color(choice: ColorChoice, no_color: bool, terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && terminal,
+    }
+}
+
+fn print_summary(summary: &Summary, color: bool) {
+    eprint!("{}", format_summary(summary, color));
+}
+
+fn format_summary(summary: &Summary, color: bool) -> String {
+    let paint = |code: &str, text: String| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text
+        }
+    };
+
+    let mut output = format!(
+        "{}, {}, {}\n",
+        paint("1;32", format!("{} devices", summary.devices)),
+        paint("1;34", format!("{} links", summary.links)),
+        paint("1;33", format!("{} unknown clients", summary.unknown)),
+    );
+
+    for warning in summary.warnings.iter() {
+        output.push_str(&format!(
+            "{} {}\n",
+            paint("1;31", "warning:".to_owned()),
+            warning
+        ));
+    }
+
+    output
+}
+
+fn watch(mut network: Network, period: Duration, color: bool) -> Result<(), Box<dyn Error>> {
+    let devices = network.device_ids();
+    let count = devices.len();
+    let mut scheduler = Scheduler::new(devices, period, Instant::now() + period);
+    let mut polled = 0;
+
+    println!("{}", network.map()?);
+    print_summary(&network.summary(), color);
+
+    while let Some(next) = scheduler.next_due() {
+        sleep(next.saturating_duration_since(Instant::now()));
+
+        for device in scheduler.due(Instant::now()) {
+            network.poll_device(&device)?;
+            polled += 1;
+        }
+
+        if polled >= count {
+            polled = 0;
+            println!("{}", network.map()?);
+            print_summary(&network.summary(), color);
+        }
+    }
+
+    Ok(())
+}
+
+fn change_line(event: &TopologyEvent) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let link = |kind: &str, link: &Link| {
+        json!({
+            "timestamp": timestamp,
+            "kind": kind,
+            "left": link.left,
+            "left_port": link.left_port,
+            "right": link.right,
+            "right_port": link.right_port,
+        })
+    };
+    let device = |kind: &str, device: &str| {
+        json!({
+            "timestamp": timestamp,
+            "kind": kind,
+            "device": device,
+        })
+    };
+
+    match event {
+        TopologyEvent::LinkAdded(l) => link("link_added", l),
+        TopologyEvent::LinkRemoved(l) => link("link_removed", l),
+        TopologyEvent::DeviceAppeared(d) => device("device_appeared", d),
+        TopologyEvent::DeviceDisappeared(d) => device("device_disappeared", d),
+    }
+    .to_string()
+}
+
+fn log_changes(mut network: Network, period: Duration) -> Result<(), Box<dyn Error>> {
+    loop {
+        sleep(period);
+        network.poll_with_observer(&mut |event| println!("{}", change_line(&event)))?;
+    }
+}
+
+/// Renders the map with Graphviz's `dot` unless DOT is wanted.
+fn render(map: String, format: MapFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+    let format = match format {
+        MapFormat::Dot => return Ok(map.into_bytes()),
+        MapFormat::Svg => "svg",
+        MapFormat::Png => "png",
+    };
+
+    let mut child = match Command::new("dot")
+        .arg(format!("-T{}", format))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "the dot command was not found, install Graphviz to write {} maps",
+                format
+            )
+            .into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Write from another thread so a large map can't fill both pipes.
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(map.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().map_err(|_| "writing to dot panicked")??;
+
+    if !output.status.success() {
+        return Err(format!(
+            "dot could not render the map: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+/// Replaces the file in one step so readers never see a partial map.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn log_diff(diff: &TopologyDiff) {
+    for device in diff.new_devices.iter() {
+        log::info!("device {} appeared.", device);
+    }
+    for device in diff.dark_devices.iter() {
+        log::info!("device {} went dark.", device);
+    }
+    for moved in diff.moved.iter() {
+        log::info!(
+            "device {} moved from {} port {} to {} port {}.",
+            moved.mac,
+            moved.from.0,
+            moved.from.1,
+            moved.to.0,
+            moved.to.1
+        );
+    }
+}
+
+/// Reloads the config into a running network.
+type Reload<'a> = &'a dyn Fn(&mut Network) -> Result<(), netmap::Error>;
+
+/// A flag set whenever the process is sent SIGHUP.
+fn hangup_flag() -> Result<Arc<AtomicBool>, Box<dyn Error>> {
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone())?;
+    Ok(hangup)
+}
+
+fn reload_on_hangup(hangup: &AtomicBool, network: &mut Network, reload: Option<Reload>) {
+    if !hangup.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    match reload {
+        Some(reload) => match reload(network) {
+            Ok(()) => log::info!("reloaded the config."),
+            Err(e) => log::error!("the config could not be reloaded: {}.", e),
+        },
+        None => log::warn!("the config cannot be reloaded when importing."),
+    }
+}
+
+fn daemon(
+    mut network: Network,
+    period: Duration,
+    output: &Path,
+    format: MapFormat,
+    state: Option<&Path>,
+    reload: Option<Reload>,
+) -> Result<(), Box<dyn Error>> {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))?;
+    }
+    let hangup = hangup_flag()?;
+
+    write_atomically(output, &render(network.map()?, format)?)?;
+
+    let mut snapshot = network.snapshot();
+    let mut next = Instant::now() + period;
+    while running.load(Ordering::SeqCst) {
+        reload_on_hangup(&hangup, &mut network, reload);
+
+        // Sleep in short steps so an interrupt is noticed promptly.
+        let now = Instant::now();
+        if now < next {
+            sleep((next - now).min(Duration::from_millis(200)));
+            continue;
+        }
+        next += period;
+
+        if let Err(e) = network.poll() {
+            log::error!("polling failed: {}.", e);
+            continue;
+        }
+
+        let latest = network.snapshot();
+        log_diff(&snapshot.diff(&latest));
+        snapshot = latest;
+
+        if let Err(e) = network
+            .map()
+            .map_err(|e| e.into())
+            .and_then(|map| render(map, format))
+            .and_then(|map| write_atomically(output, &map))
+        {
+            log::error!("the map could not be written: {}.", e);
+        }
+    }
+
+    log::info!("shutting down.");
+    if let Some(state) = state {
+        network.save_state(state)?;
+    }
+    Ok(())
+}
+
+/// The responses for each path, rebuilt after every poll.
+struct Pages {
+    html: Result<String, String>,
+    dot: Result<String, String>,
+    json: String,
+    metrics: String,
+}
+
+impl Pages {
+    fn build(network: &Network) -> Self {
+        Pages {
+            html: network.map_html().map_err(|e| e.to_string()),
+            dot: network.map().map_err(|e| e.to_string()),
+            json: network.map_json(),
+            metrics: network.metrics(),
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, pages: &Pages, refresh: u64) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&*stream).read_line(&mut request)?;
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, content_type, body) = match path {
+        "/" => match &pages.html {
+            Ok(html) => ("200 OK", "text/html; charset=utf-8", html.as_str()),
+            Err(e) => ("500 Internal Server Error", "text/plain", e.as_str()),
+        },
+        "/map.dot" => match &pages.dot {
+            Ok(dot) => ("200 OK", "text/vnd.graphviz", dot.as_str()),
+            Err(e) => ("500 Internal Server Error", "text/plain", e.as_str()),
+        },
+        "/map.json" => ("200 OK", "application/json", pages.json.as_str()),
+        "/metrics" => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            pages.metrics.as_str(),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found"),
+    };
+
+    // The page reloads itself once the next poll is due.
+    let refresh = if path == "/" {
+        format!("Refresh: {}\r\n", refresh)
+    } else {
+        String::new()
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        refresh,
+        body
+    )?;
+    Ok(())
+}
+
+/// Serves the map over HTTP, polling between requests.
+fn serve(
+    mut network: Network,
+    period: Duration,
+    address: SocketAddr,
+    reload: Option<Reload>,
+) -> Result<(), Box<dyn Error>> {
+    let hangup = hangup_flag()?;
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    log::info!("serving the map on http://{}/.", address);
+
+    let mut pages = Pages::build(&network);
+    let mut snapshot = network.snapshot();
+    let mut next = Instant::now() + period;
+    loop {
+        reload_on_hangup(&hangup, &mut network, reload);
+
+        if Instant::now() >= next {
+            next += period;
+            match network.poll() {
+                Ok(_) => {
+                    let latest = network.snapshot();
+                    log_diff(&snapshot.diff(&latest));
+                    snapshot = latest;
+                    pages = Pages::build(&network);
+                }
+                Err(e) => log::error!("polling failed: {}.", e),
+            }
+        }
+
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                if let Err(e) = respond(&mut stream, &pages, period.as_secs()) {
+                    log::warn!("a request failed: {}.", e);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// The options given on the command line, applied over those in the config.
+struct Overrides {
+    snapshot: bool,
+    compact: bool,
+    reverse_dns: bool,
+    rankdir: Option<String>,
+    label: Option<String>,
+}
+
+impl Overrides {
+    fn apply(&self, options: &mut MapOptions) {
+        if self.snapshot {
+            options.snapshot = true;
+        }
+        if self.compact {
+            options.compact = true;
+        }
+        if self.reverse_dns {
+            options.reverse_dns = true;
+        }
+        if let Some(rankdir) = &self.rankdir {
+            options.graph.rankdir = Some(rankdir.clone());
+        }
+        if let Some(label) = &self.label {
+            options.graph.label = Some(label.clone());
+        }
+    }
+}
+
+/// The config files to load, `network.json` when none are given, and
+/// whether they were given.
+fn config_paths(files: &[PathBuf]) -> (Vec<PathBuf>, bool) {
+    let explicit = !files.is_empty();
+    let files = if explicit {
+        files.to_vec()
+    } else {
+        vec![PathBuf::from("network.json")]
+    };
+    let paths = files
+        .iter()
+        .map(|file| current_dir().unwrap().join(file))
+        .collect();
+    (paths, explicit)
+}
+
+/// Where a network's config came from so it can be loaded again.
+struct ConfigSource {
+    paths: Vec<PathBuf>,
+    overrides: Overrides,
+    importing: bool,
+}
+
+impl ConfigSource {
+    fn reload(&self, network: &mut Network) -> Result<(), netmap::Error> {
+        let paths: Vec<&Path> = self.paths.iter().map(|p| p.as_path()).collect();
+        let mut config = NetworkConfig::load_many(&paths)?;
+        self.overrides.apply(&mut config.options);
+        network.reload(config)
+    }
+}
+
+fn open(args: NetworkArgs) -> Result<(Network, ConfigSource), Box<dyn Error>> {
+    let (paths, explicit) = config_paths(&args.files);
+    let path = &paths[0];
+    let overrides = Overrides {
+        snapshot: args.snapshot,
+        compact: args.compact,
+        reverse_dns: args.reverse_dns,
+        rankdir: args.rankdir,
+        label: args.label,
+    };
+    let importing = args.import_dir.is_some();
+
+    let mut network = match args.import_dir {
+        Some(dir) => {
+            // The config is optional when importing, it only adds detail.
+            let mut config = if explicit || path.exists() {
+                NetworkConfig::load(path)?
+            } else {
+                NetworkConfig::default()
+            };
+            config.import_dir(&current_dir().unwrap().join(dir))?;
+            config.options.snapshot = true;
+            Network::new(config, path.parent().unwrap())
+        }
+        None => {
+            let paths: Vec<&Path> = paths.iter().map(|p| p.as_path()).collect();
+            Network::try_from_many(&paths)?
+        }
+    };
+    overrides.apply(network.options_mut());
+
+    Ok((
+        network,
+        ConfigSource {
+            paths,
+            overrides,
+            importing,
+        },
+    ))
+}
+
+/// Polls and then keeps polling, serving the map when given an address and
+/// otherwise writing it to the output file.
+fn keep_polling(
+    mut network: Network,
+    source: &ConfigSource,
+    period: Duration,
+    listen: Option<SocketAddr>,
+    output: Option<(&Path, MapFormat)>,
+    state: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let reload_config = |network: &mut Network| source.reload(network);
+    let reload: Option<Reload> = if source.importing {
+        None
+    } else {
+        Some(&reload_config)
+    };
+    let period = Duration::from_secs(period.as_secs().max(1));
+
+    if let Some(address) = listen {
+        if let Err(e) = network.poll() {
+            log::error!("polling failed: {}.", e);
+        }
+        return serve(network, period, address, reload);
+    }
+
+    let state = state.map(|state| current_dir().unwrap().join(state));
+    if let Some(state) = state.as_deref().filter(|state| state.exists()) {
+        if let Err(e) = network.load_state(state) {
+            log::warn!("the saved state could not be restored: {}.", e);
+        }
+    }
+    if let Err(e) = network.poll() {
+        log::error!("polling failed: {}.", e);
+    }
+    let (output, format) = output.ok_or("there is nowhere to write the map")?;
+    daemon(
+        network,
+        period,
+        &current_dir().unwrap().join(output),
+        format,
+        state.as_deref(),
+        reload,
+    )
+}
+
+fn poll(args: PollArgs) -> Result<(), Box<dyn Error>> {
+    let (mut network, source) = open(args.network)?;
+
+    if args.listen.is_some() || args.interval.is_some() {
+        return keep_polling(
+            network,
+            &source,
+            Duration::from_secs(args.interval.unwrap_or(60)),
+            args.listen,
+            args.output.as_deref().map(|output| (output, args.format)),
+            args.state.as_deref(),
+        );
+    }
+
+    network.poll()?;
+
+    if let Some(period) = args.watch {
+        let period = Duration::from_secs(period.max(1));
+        if args.log_changes {
+            return log_changes(network, period);
+        }
+
+        return watch(network, period, use_color(args.color));
+    }
+
+    if args.verify {
+        let discrepancies = network.verify(network.expected());
+        for discrepancy in discrepancies.iter() {
+            println!("{}", discrepancy);
+        }
+
+        if !discrepancies.is_empty() {
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    let map = render(network.map()?, args.format)?;
+    match &args.output {
+        Some(output) => fs::write(current_dir().unwrap().join(output), map)?,
+        None if args.format == MapFormat::Dot => println!("{}", String::from_utf8(map)?),
+        None => stdout().write_all(&map)?,
+    }
+    let summary = network.summary();
+    print_summary(&summary, use_color(args.color));
+
+    if args.fail_if_empty && summary.links == 0 && summary.unknown == 0 {
+        exit(2);
+    }
+
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let (mut network, _) = open(args.network)?;
+    network.poll()?;
+
+    let map = match args.format {
+        ExportFormat::Json => network.map_json(),
+        ExportFormat::Dot => network.map()?,
+        ExportFormat::Graphml => network.map_graphml(),
+        ExportFormat::Mermaid => network.map_mermaid(),
+    };
+    match &args.output {
+        Some(output) => fs::write(current_dir().unwrap().join(output), map)?,
+        None => println!("{}", map),
+    }
+
+    Ok(())
+}
+
+fn validate(args: ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let (paths, _) = config_paths(&args.files);
+    let paths: Vec<&Path> = paths.iter().map(|p| p.as_path()).collect();
+    let config = match NetworkConfig::load_many(&paths).and_then(|config| {
+        config.validate()?;
+        Ok(config)
+    }) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+
+    let devices = config.devices.len();
+    let network = Network::new(config, paths[0].parent().unwrap());
+    for warning in network.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    println!("the config is valid, {} devices are configured.", devices);
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -17,12 +822,100 @@ fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
     let cli = Cli::parse();
 
-    let mut path = current_dir().unwrap();
-    path.push(cli.file.unwrap_or_else(|| PathBuf::from("network.json")));
+    match cli.command {
+        None => poll(cli.poll),
+        Some(Commands::Poll(args)) => poll(args),
+        Some(Commands::Serve(args)) => {
+            let (network, source) = open(args.network)?;
+            keep_polling(
+                network,
+                &source,
+                Duration::from_secs(args.interval),
+                args.listen,
+                args.output.as_deref().map(|output| (output, args.format)),
+                args.state.as_deref(),
+            )
+        }
+        Some(Commands::Export(args)) => export(args),
+        Some(Commands::Validate(args)) => validate(args),
+    }
+}
 
-    let mut network = Network::try_from(path.as_ref())?;
-    network.poll()?;
-    println!("{}", network.map());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    fn summary() -> Summary {
+        Summary {
+            devices: 3,
+            links: 2,
+            unknown: 1,
+            warnings: vec!["a warning.".to_owned()],
+        }
+    }
+
+    #[test]
+    fn summary_is_plain_without_color() {
+        assert!(!wants_color(ColorChoice::Auto, true, true));
+        assert!(!wants_color(ColorChoice::Auto, false, false));
+        assert!(wants_color(ColorChoice::Auto, false, true));
+
+        let plain = format_summary(&summary(), false);
+        assert!(!plain.contains('\x1b'));
+        assert_eq!(
+            plain,
+            "3 devices, 2 links, 1 unknown clients\nwarning: a warning.\n"
+        );
+        assert!(format_summary(&summary(), true).contains('\x1b'));
+    }
+
+    #[test]
+    fn changes_are_logged_as_json_lines() {
+        let root = env::temp_dir().join(format!("netmap-change-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("sw.fdb"), "").unwrap();
+
+        let config: NetworkConfig = serde_json::from_str(
+            r#"{
+                "devices": [
+                    {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                     "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 0}]},
+                    {"id": "r", "mac": ["00:11:22:33:44:02"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut network = Network::new(config, &root);
+        let mut lines = Vec::new();
+        network
+            .poll_with_observer(&mut |event| lines.push(change_line(&event)))
+            .unwrap();
+        assert!(lines.is_empty());
+
+        fs::write(root.join("sw.fdb"), "00:11:22:33:44:02 dev lan1\n").unwrap();
+        lines.clear();
+        network
+            .poll_with_observer(&mut |event| lines.push(change_line(&event)))
+            .unwrap();
+        let events: Vec<serde_json::Value> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let added = events.iter().find(|e| e["kind"] == "link_added").unwrap();
+        assert!(added["timestamp"].is_u64());
+        assert_eq!(
+            (&added["left"], &added["right"], &added["right_port"]),
+            (&json!("r"), &json!("sw"), &json!("lan1"))
+        );
+
+        fs::write(root.join("sw.fdb"), "").unwrap();
+        lines.clear();
+        network
+            .poll_with_observer(&mut |event| lines.push(change_line(&event)))
+            .unwrap();
+        assert!(lines
+            .iter()
+            .any(|line| line.contains(r#""kind":"link_removed""#)));
+    }
 }
@@ -0,0 +1,52 @@
+use crate::layout::{Layout, NodeKind};
+
+/// Mermaid labels are quoted, so quotes are written as entities.
+fn escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br>")
+}
+
+/// Writes the layout as a Mermaid flowchart, groups become subgraphs.
+pub fn render(layout: &Layout) -> String {
+    let mut output = String::from("graph LR\n");
+
+    // Ids only need to be unique, the labels carry the names.
+    let write_node = |output: &mut String, index: usize, indent: &str| {
+        let node = &layout.nodes[index];
+        let label = escape(&node.label);
+        let shape = match node.kind {
+            NodeKind::Device => format!("[\"{}\"]", label),
+            NodeKind::Port => format!("([\"{}\"])", label),
+            NodeKind::Client => format!("(\"{}\")", label),
+            NodeKind::Aggregate => format!("{{{{\"{}\"}}}}", label),
+        };
+        output.push_str(&format!("{}n{}{}\n", indent, index, shape));
+    };
+
+    for (group, label) in layout.groups.iter().enumerate() {
+        output.push_str(&format!("  subgraph g{} [\"{}\"]\n", group, escape(label)));
+        for (index, _) in layout
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.group == Some(group))
+        {
+            write_node(&mut output, index, "    ");
+        }
+        output.push_str("  end\n");
+    }
+
+    for (index, _) in layout
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.group.is_none())
+    {
+        write_node(&mut output, index, "  ");
+    }
+
+    for (source, target) in layout.edges.iter() {
+        output.push_str(&format!("  n{} --- n{}\n", source, target));
+    }
+
+    output
+}
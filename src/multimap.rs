@@ -4,7 +4,8 @@ use std::hash::Hash;
 #[derive(Clone)]
 pub struct MultiMap<K, V> {
     next_index: usize,
-    indexes: HashMap<K, usize>,
+    /// Several values can share a key, like gateways sharing a virtual MAC.
+    indexes: HashMap<K, Vec<usize>>,
     values: HashMap<usize, V>,
 }
 
@@ -27,7 +28,27 @@ where
 
         self.values.insert(index, value);
         for k in keys.into_iter() {
-            self.indexes.insert(k, index);
+            let indexes = self.indexes.entry(k).or_default();
+            if !indexes.contains(&index) {
+                indexes.push(index);
+            }
+        }
+    }
+
+    /// Attaches another key to the value at an index from `position`. The
+    /// key is left alone and this returns false when there is no such value
+    /// or the key already belongs to other values.
+    pub fn add_key_at(&mut self, index: usize, key: K) -> bool {
+        if !self.values.contains_key(&index) {
+            return false;
+        }
+
+        match self.indexes.get(&key) {
+            Some(others) => others.contains(&index),
+            None => {
+                self.indexes.insert(key, vec![index]);
+                true
+            }
         }
     }
 
@@ -43,16 +64,100 @@ where
         self.values.values_mut()
     }
 
+    /// The index of the earliest inserted value the predicate accepts, which
+    /// stays the same for as long as the value is stored.
+    pub fn position<F>(&self, accept: F) -> Option<usize>
+    where
+        F: Fn(&V) -> bool,
+    {
+        self.values
+            .iter()
+            .filter(|(_, value)| accept(value))
+            .map(|(index, _)| *index)
+            .min()
+    }
+
+    pub fn get_at_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.values.get_mut(&index)
+    }
+
+    /// Each value along with every key pointing at it.
+    pub fn entries(&self) -> impl Iterator<Item = (Vec<&K>, &V)> {
+        let mut keys: HashMap<usize, Vec<&K>> = HashMap::new();
+        for (key, indexes) in self.indexes.iter() {
+            for index in indexes {
+                keys.entry(*index).or_default().push(key);
+            }
+        }
+
+        self.values
+            .iter()
+            .map(move |(index, value)| (keys.remove(index).unwrap_or_default(), value))
+    }
+
+    /// The first value inserted with the key.
     pub fn get(&self, key: &K) -> Option<&V> {
-        self.indexes.get(key).and_then(|idx| self.values.get(idx))
+        self.indexes
+            .get(key)
+            .and_then(|idx| idx.first())
+            .and_then(|idx| self.values.get(idx))
     }
 
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+    /// Every value sharing the key in the order they were inserted.
+    pub fn get_all(&self, key: &K) -> impl Iterator<Item = &V> {
         self.indexes
             .get(key)
-            .and_then(|idx| self.values.get_mut(idx))
+            .into_iter()
+            .flatten()
+            .filter_map(|idx| self.values.get(idx))
+    }
+
+    /// Removes the first value inserted with the key along with every key
+    /// pointing at it.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = *self.indexes.get(key)?.first()?;
+        self.unindex(|i| i == index);
+        self.values.remove(&index)
+    }
+
+    /// Keeps only the values the predicate accepts, dropping the keys of the
+    /// rest.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&V) -> bool,
+    {
+        let removed: Vec<usize> = self
+            .values
+            .iter()
+            .filter(|(_, value)| !keep(value))
+            .map(|(index, _)| *index)
+            .collect();
+        if removed.is_empty() {
+            return;
+        }
+
+        for index in removed.iter() {
+            self.values.remove(index);
+        }
+        self.unindex(|i| removed.contains(&i));
     }
 
+    /// Drops the matching value indexes from every key, forgetting keys left
+    /// without a value.
+    fn unindex<F>(&mut self, removed: F)
+    where
+        F: Fn(usize) -> bool,
+    {
+        self.indexes.retain(|_, indexes| {
+            indexes.retain(|i| !removed(*i));
+            !indexes.is_empty()
+        });
+    }
+
+    /// Visits every pair of values, too slow for large maps but simple
+    /// enough to check faster searches against.
+    #[cfg(test)]
     pub fn visit_pairs<F>(&mut self, mut visit: F)
     where
         F: FnMut(&mut V, &mut V),
@@ -94,3 +199,100 @@ where
         map
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MultiMap;
+
+    #[test]
+    fn shared_keys_resolve_to_every_value() {
+        let mut map = MultiMap::default();
+        map.insert(["shared", "gw1"], "first");
+        map.insert(["shared", "gw2"], "second");
+
+        assert!(map.contains_key(&"shared"));
+        assert!(!map.contains_key(&"missing"));
+        assert_eq!(map.get(&"shared"), Some(&"first"));
+        assert_eq!(map.get(&"gw2"), Some(&"second"));
+        assert_eq!(map.get(&"missing"), None);
+        assert_eq!(
+            map.get_all(&"shared").collect::<Vec<_>>(),
+            vec![&"first", &"second"]
+        );
+        assert_eq!(map.get_all(&"missing").count(), 0);
+    }
+
+    #[test]
+    fn from_iter_keeps_every_value() {
+        let map: MultiMap<&str, u32> = [(vec!["a", "b"], 1), (vec!["b"], 2), (vec!["c"], 3)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(map.values().count(), 3);
+        assert_eq!(map.get_all(&"b").collect::<Vec<_>>(), vec![&1, &2]);
+        assert_eq!(map.get(&"a"), Some(&1));
+        assert_eq!(map.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn add_key_at_leaves_other_values_keys_alone() {
+        let mut map = MultiMap::default();
+        map.insert(["a"], "first");
+        map.insert(["b"], "second");
+        let first = map.position(|v| *v == "first").unwrap();
+
+        assert!(map.add_key_at(first, "c"));
+        assert_eq!(map.get(&"c"), Some(&"first"));
+        assert!(!map.add_key_at(first, "b"));
+        assert_eq!(map.get_all(&"b").collect::<Vec<_>>(), vec![&"second"]);
+        assert!(!map.add_key_at(first + 10, "d"));
+        assert!(!map.contains_key(&"d"));
+    }
+
+    #[test]
+    fn visit_pairs_visits_each_pair_once() {
+        let count = |size: u32| {
+            let mut map: MultiMap<u32, u32> = (0..size).map(|i| ([i], i)).collect();
+            let mut pairs = Vec::new();
+            map.visit_pairs(|left, right| pairs.push((*left.min(right), *left.max(right))));
+            pairs.sort();
+            pairs
+        };
+
+        assert!(count(0).is_empty());
+        assert!(count(1).is_empty());
+        assert_eq!(count(3), vec![(0, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn remove_forgets_every_key_of_the_value() {
+        let mut map = MultiMap::default();
+        map.insert(["a", "b", "shared"], 1);
+        map.insert(["shared", "c"], 2);
+
+        assert_eq!(map.remove(&"b"), Some(1));
+        assert!(!map.contains_key(&"a"));
+        assert!(!map.contains_key(&"b"));
+        assert_eq!(map.get_all(&"shared").collect::<Vec<_>>(), vec![&2]);
+        assert_eq!(map.get(&"c"), Some(&2));
+        assert_eq!(map.indexes.len(), 2);
+        assert_eq!(map.remove(&"a"), None);
+    }
+
+    #[test]
+    fn retain_forgets_the_keys_of_dropped_values() {
+        let mut map = MultiMap::default();
+        map.insert(["a", "shared"], 1);
+        map.insert(["b", "shared"], 2);
+        map.insert(["c"], 3);
+
+        map.retain(|value| value % 2 == 1);
+
+        assert_eq!(map.values().count(), 2);
+        assert!(!map.contains_key(&"b"));
+        assert_eq!(map.get_all(&"shared").collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(map.get(&"a"), Some(&1));
+        assert_eq!(map.get(&"c"), Some(&3));
+        assert_eq!(map.indexes.len(), 3);
+    }
+}
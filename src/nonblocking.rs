@@ -0,0 +1,49 @@
+use std::{future::Future, path::Path, time::Duration};
+
+use crate::error::Error;
+
+/// Reads a whole file without blocking the runtime.
+pub async fn read_file(path: &Path) -> Result<String, Error> {
+    let bytes = tokio::fs::read(path).await.map_err(Error::IoError)?;
+    let data = crate::parsers::decode_file(path, bytes)?;
+    if data.is_empty() {
+        return Err(Error::EmptyFile(path.display().to_string()));
+    }
+    Ok(data)
+}
+
+/// Runs a local command from the network's root directory without blocking the
+/// runtime and returns whatever it wrote to stdout.
+pub async fn run(command: &str, args: &[String], root: &Path) -> Result<String, Error> {
+    let output = tokio::process::Command::new(command)
+        .args(args)
+        .current_dir(root)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| Error::CommandError(format!("`{}` could not be run: {}", command, e)))?;
+
+    if !output.status.success() {
+        return Err(Error::CommandError(format!(
+            "`{}` exited with {}",
+            command, output.status
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Gives up on a poller that takes too long, returning nothing so whatever it
+/// last found expires as usual.
+pub async fn within<T, F>(timeout: Duration, description: &str, poll: F) -> Option<T>
+where
+    F: Future<Output = T>,
+{
+    match tokio::time::timeout(timeout, poll).await {
+        Ok(result) => Some(result),
+        Err(_) => {
+            log::warn!("{} timed out after {:?}.", description, timeout);
+            None
+        }
+    }
+}
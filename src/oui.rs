@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+use eui48::MacAddress;
+
+/// A few widely seen organisationally unique identifiers.
+const VENDORS: &[([u8; 3], &str)] = &[
+    ([0x00, 0x00, 0x0c], "Cisco"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x0a, 0x95], "Apple"),
+    ([0x00, 0x0c, 0x29], "VMware"),
+    ([0x00, 0x11, 0x32], "Synology"),
+    ([0x00, 0x15, 0x5d], "Microsoft"),
+    ([0x00, 0x1a, 0x11], "Google"),
+    ([0x00, 0x1b, 0x21], "Intel"),
+    ([0x00, 0x1b, 0x63], "Apple"),
+    ([0x00, 0x50, 0x56], "VMware"),
+    ([0x08, 0x00, 0x27], "VirtualBox"),
+    ([0x18, 0xe8, 0x29], "Ubiquiti"),
+    ([0x24, 0xa4, 0x3c], "Ubiquiti"),
+    ([0x3c, 0x5a, 0xb4], "Google"),
+    ([0xb8, 0x27, 0xeb], "Raspberry Pi"),
+    ([0xdc, 0xa6, 0x32], "Raspberry Pi"),
+    ([0xfc, 0xec, 0xda], "Ubiquiti"),
+];
+
+fn parse_prefix(prefix: &str) -> Option<[u8; 3]> {
+    let bytes: Vec<u8> = prefix
+        .split([':', '-'])
+        .map(|b| u8::from_str_radix(b, 16).ok())
+        .collect::<Option<_>>()?;
+    bytes.try_into().ok()
+}
+
+/// Whether a prefix like `00:11:22` is a valid OUI.
+pub fn is_prefix(prefix: &str) -> bool {
+    parse_prefix(prefix).is_some()
+}
+
+/// Whether some hardware's OUI is the given prefix.
+pub fn has_prefix(mac: &MacAddress, prefix: &str) -> bool {
+    let bytes = mac.to_array();
+    parse_prefix(prefix) == Some([bytes[0], bytes[1], bytes[2]])
+}
+
+/// Finds the vendor of some hardware from its OUI, preferring any vendors the
+/// config supplies (keyed by prefixes like `00:11:22`).
+pub fn vendor(mac: &MacAddress, overrides: &BTreeMap<String, String>) -> Option<String> {
+    let bytes = mac.to_array();
+    let oui = [bytes[0], bytes[1], bytes[2]];
+
+    for (prefix, vendor) in overrides.iter() {
+        if parse_prefix(prefix) == Some(oui) {
+            return Some(vendor.clone());
+        }
+    }
+
+    VENDORS
+        .iter()
+        .find(|(prefix, _)| *prefix == oui)
+        .map(|(_, vendor)| (*vendor).to_owned())
+}
+
+/// Describes unknown hardware by its vendor, hardware using a locally
+/// administered address is usually randomising it for privacy.
+pub fn describe(mac: &MacAddress, overrides: &BTreeMap<String, String>) -> String {
+    if !mac.is_universal() {
+        return "randomized".to_owned();
+    }
+    vendor(mac, overrides).unwrap_or_else(|| "unknown".to_owned())
+}
@@ -1,19 +1,131 @@
 use std::{
+    cell::{Cell, RefCell},
     collections::{HashMap, HashSet},
     fs::File,
-    io::Read,
-    path::Path,
+    io::{self, stdin, Read, Seek, SeekFrom},
+    net::IpAddr,
+    path::{Path, PathBuf},
     str::FromStr,
-    time::{Duration, Instant},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(feature = "http")]
+use std::collections::BTreeMap;
+
 use eui48::MacAddress;
-use serde::Deserialize;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    expiry::{ExpireSet, Metadata},
+};
 
-use crate::{error::Error, expiry::ExpireSet};
+/// How long hardware stays visible when neither a poller nor the network
+/// says otherwise.
+pub(crate) const DEFAULT_EXPIRY: Duration = Duration::from_secs(5);
 
-fn is_valid_mac(mac: MacAddress) -> bool {
-    mac.is_universal() && mac.is_unicast()
+fn expiry(expiry_secs: Option<u64>, default: Duration) -> Duration {
+    expiry_secs.map(Duration::from_secs).unwrap_or(default)
+}
+
+/// How long to wait for a file or command before giving up.
+fn io_timeout(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(timeout_secs.unwrap_or(5))
+}
+
+/// How long to wait for a remote agent before giving up.
+#[cfg(feature = "http")]
+fn http_timeout(timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(timeout_secs.unwrap_or(10))
+}
+
+/// Locally administered addresses are kept as phones randomise theirs, the
+/// config decides whether they are ignored.
+pub(crate) fn is_valid_mac(mac: MacAddress) -> bool {
+    mac.is_unicast() && !mac.is_nil()
+}
+
+/// Whether a port is a VLAN sub-interface like `eth0.100`.
+fn is_vlan_interface(port: &str) -> bool {
+    match port.rsplit_once('.') {
+        Some((_, vlan)) => !vlan.is_empty() && vlan.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Splits a line of output into its whitespace separated tokens.
+fn tokenize_line(line: &str) -> Vec<&str> {
+    line.split_whitespace().collect()
+}
+
+/// Whether a port belongs to a bridge, directly or through nested bridges.
+fn is_bridge_member(masters: &HashMap<String, String>, port: &str, bridge: &str) -> bool {
+    let mut current = port;
+    // Bounded in case the masters form a loop.
+    for _ in 0..masters.len() {
+        match masters.get(current) {
+            Some(master) if master == bridge => return true,
+            Some(master) => current = master,
+            None => return false,
+        }
+    }
+
+    false
+}
+
+/// Expands a swconfig port list like `1,2` or range like `1-4`, or a mix of
+/// the two, into the individual ports.
+fn expand_port_spec(spec: &str) -> Option<Vec<String>> {
+    let mut ports = Vec::new();
+    for part in spec.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: u32 = start.parse().ok()?;
+                let end: u32 = end.parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                ports.extend((start..=end).map(|port| port.to_string()));
+            }
+            None if part.is_empty() => return None,
+            None => ports.push(part.to_owned()),
+        }
+    }
+
+    Some(ports)
+}
+
+/// Parses a hardware address written as six colon or hyphen separated pairs
+/// or, as Cisco devices do, three dot separated groups of four.
+fn parse_mac_token(token: &str) -> Option<MacAddress> {
+    let groups: Vec<&str> = if token.contains('.') {
+        token.split('.').collect()
+    } else {
+        token.split([':', '-']).collect()
+    };
+
+    let valid = match groups.len() {
+        3 => groups.iter().all(|g| g.len() == 4),
+        6 => groups.iter().all(|g| g.len() == 2),
+        _ => false,
+    };
+    if !valid
+        || !groups
+            .iter()
+            .all(|g| g.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+
+    let hex: String = groups.concat();
+    let mut bytes = [0; 6];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(MacAddress::new(bytes))
 }
 
 macro_rules! unwrap_option_or_continue {
@@ -36,73 +148,659 @@ macro_rules! unwrap_result_or_continue {
     };
 }
 
-fn parse_port_data(data: String, _format: PortDataFormat) -> Result<ExpireSet<MacAddress>, Error> {
-    let mut set = ExpireSet::default();
-    let expiry = Instant::now() + Duration::from_secs(5);
+pub type DeviceParser = Box<dyn Fn(&str) -> Result<HashMap<String, ExpireSet<MacAddress>>, Error>>;
+pub type PortParser = Box<dyn Fn(&str) -> Result<ExpireSet<MacAddress>, Error>>;
+
+/// Custom formats registered at runtime.
+#[derive(Default)]
+pub struct Formats {
+    pub device: HashMap<String, DeviceParser>,
+    pub port: HashMap<String, PortParser>,
+}
+
+/// Shared state for a single poll.
+pub struct Context<'a> {
+    pub root: &'a Path,
+    pub formats: &'a Formats,
+    /// The time to record observations at, defaults to now.
+    pub reference: Option<Instant>,
+    /// How far into each tailed file has already been parsed.
+    pub offsets: &'a RefCell<HashMap<PathBuf, u64>>,
+    /// Whether a poller has already drained stdin.
+    pub stdin_read: &'a Cell<bool>,
+    /// How long hardware stays visible when a poller doesn't say otherwise.
+    pub default_expiry: Duration,
+}
+
+impl<'a> Context<'a> {
+    /// Runs a function with a context that only knows about the built in
+    /// formats.
+    fn detached<T>(f: impl FnOnce(&Context) -> T) -> T {
+        let offsets = RefCell::new(HashMap::new());
+        let stdin_read = Cell::new(false);
+        let context = Context {
+            root: Path::new(""),
+            formats: &Formats::default(),
+            reference: None,
+            offsets: &offsets,
+            stdin_read: &stdin_read,
+            default_expiry: DEFAULT_EXPIRY,
+        };
+        f(&context)
+    }
+
+    fn now(&self) -> Instant {
+        self.reference.unwrap_or_else(Instant::now)
+    }
+}
+
+/// Where an observation came from.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    ForwardDb,
+    Arp,
+    Wireless,
+    Capture,
+    Lldp,
+    Custom(String),
+}
+
+impl Source {
+    /// How much an observation from this source can be trusted to indicate a
+    /// direct link.
+    pub fn authority(&self) -> u8 {
+        match self {
+            Source::Lldp => 3,
+            Source::ForwardDb | Source::Wireless => 2,
+            Source::Capture | Source::Custom(_) => 1,
+            Source::Arp => 0,
+        }
+    }
+
+    /// The edge style used for links established by this source.
+    pub fn style(&self) -> Option<&'static str> {
+        match self {
+            Source::ForwardDb | Source::Wireless | Source::Lldp => None,
+            Source::Capture | Source::Custom(_) => Some("dotted"),
+            Source::Arp => Some("dashed"),
+        }
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn has_gzip_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Turns a file's contents into text, decompressing it first if it is gzipped.
+pub(crate) fn decode_file(path: &Path, bytes: Vec<u8>) -> Result<String, Error> {
+    if has_gzip_extension(path) || bytes.starts_with(&GZIP_MAGIC) {
+        let mut data = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut data)
+            .map_err(Error::DecompressError)?;
+        return Ok(data);
+    }
+
+    to_text(bytes)
+}
+
+fn to_text(bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes)
+        .map_err(|e| Error::IoError(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+/// Runs blocking work on another thread, giving up on it after the timeout.
+/// Work that never finishes, like reading a FIFO nothing writes to, is left
+/// behind.
+fn within<T, F>(timeout: Duration, description: String, work: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(Error::Timeout(description)),
+        Err(RecvTimeoutError::Disconnected) => Err(Error::IoError(io::Error::other(format!(
+            "{} failed",
+            description
+        )))),
+    }
+}
+
+fn read_file(
+    path: PathBuf,
+    tail: bool,
+    timeout: Duration,
+    context: &Context,
+) -> Result<String, Error> {
+    // Compressed files are always read whole, there is no way to pick up where
+    // the last read stopped.
+    let whole = !tail || has_gzip_extension(&path);
+    let offset = match whole {
+        true => 0,
+        false => context.offsets.borrow().get(&path).copied().unwrap_or(0),
+    };
+
+    let reading = path.clone();
+    let (bytes, start) = within(timeout, format!("reading {}", path.display()), move || {
+        let mut file = File::open(&reading).map_err(Error::IoError)?;
+
+        // Start again if the file has been truncated or replaced.
+        let len = file.metadata().map_err(Error::IoError)?.len();
+        let start = if len < offset { 0 } else { offset };
+        file.seek(SeekFrom::Start(start)).map_err(Error::IoError)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(Error::IoError)?;
+        Ok((bytes, start))
+    })?;
+
+    if whole {
+        let data = decode_file(&path, bytes)?;
+        if data.is_empty() {
+            return Err(Error::EmptyFile(path.display().to_string()));
+        }
+        return Ok(data);
+    }
+
+    // Leave any partially written line for the next poll.
+    let mut data = to_text(bytes)?;
+    let complete = data.rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+    data.truncate(complete);
+    context
+        .offsets
+        .borrow_mut()
+        .insert(path, start + complete as u64);
+
+    Ok(data)
+}
+
+/// Reads everything piped to stdin, which can only happen once per run.
+fn read_stdin(context: &Context) -> Result<String, Error> {
+    if context.stdin_read.replace(true) {
+        return Err(Error::StdinError(
+            "stdin was already read, only one poller can read it and only once per run".to_owned(),
+        ));
+    }
+
+    let mut data = String::new();
+    stdin().read_to_string(&mut data).map_err(Error::IoError)?;
+    Ok(data)
+}
+
+/// A DHCP lease handed out to some hardware.
+pub struct Lease {
+    pub mac: MacAddress,
+    pub address: IpAddr,
+    pub hostname: Option<String>,
+}
+
+/// Parses a dnsmasq leases file, where lines look like
+/// `<expiry> <mac> <ip> <hostname> <client id>`, skipping expired leases.
+pub fn parse_leases(data: &str, now: SystemTime) -> Vec<Lease> {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut leases = Vec::new();
 
     for line in data.split('\n') {
-        if line.len() != 17 {
+        let parts = tokenize_line(line);
+        if parts.len() < 4 {
             continue;
         }
 
-        if line.chars().nth(2) != Some(':') {
+        // Leases that never expire are written with an expiry of 0.
+        let expiry = unwrap_result_or_continue!(parts[0].parse::<u64>());
+        if expiry != 0 && expiry < now {
             continue;
         }
 
-        let mac = unwrap_result_or_continue!(MacAddress::from_str(line));
-        log::trace!("hostapd reported hardware {}", mac);
-        set.insert(mac, expiry);
+        let mac = unwrap_option_or_continue!(parse_mac_token(parts[1]));
+        let address = unwrap_result_or_continue!(IpAddr::from_str(parts[2]));
+        let hostname = Some(parts[3]).filter(|h| *h != "*").map(str::to_owned);
+
+        log::trace!("leases reported hardware {}", mac);
+        leases.push(Lease {
+            mac,
+            address,
+            hostname,
+        });
+    }
+
+    leases
+}
+
+/// A station as reported by hostapd's `all_sta` JSON output.
+#[derive(Deserialize)]
+struct Station {
+    mac: MacAddress,
+    #[serde(default)]
+    connected_time: Option<u64>,
+    #[serde(default)]
+    signal: Option<i64>,
+}
+
+fn insert_station(
+    set: &mut ExpireSet<MacAddress>,
+    mac: MacAddress,
+    metadata: Metadata,
+    expiry: Instant,
+) {
+    if !is_valid_mac(mac) {
+        return;
+    }
+
+    log::trace!(
+        "hostapd reported hardware {} (connected {:?}s, signal {:?})",
+        mac,
+        metadata.connected_secs,
+        metadata.signal
+    );
+    let metadata = Some(metadata).filter(|m| *m != Metadata::default());
+    set.insert_with_metadata(mac, expiry, metadata);
+}
+
+fn parse_port_data(
+    data: &str,
+    format: &PortDataFormat,
+    ttl: Duration,
+    context: &Context,
+) -> Result<ExpireSet<MacAddress>, Error> {
+    let mut set = ExpireSet::default();
+    let now = context.now();
+
+    match format {
+        PortDataFormat::HostApd => {
+            // Each station's MAC is followed by `key=value` lines describing it.
+            let mut station: Option<(MacAddress, Metadata)> = None;
+            for line in data.split('\n').map(str::trim) {
+                if let Some((key, value)) = line.split_once('=') {
+                    if let Some((_, metadata)) = station.as_mut() {
+                        match key {
+                            "signal" => metadata.signal = value.parse().ok(),
+                            "connected_time" => metadata.connected_secs = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+
+                let mac = unwrap_option_or_continue!(parse_mac_token(line));
+                if let Some((mac, metadata)) = station.replace((mac, Metadata::default())) {
+                    insert_station(&mut set, mac, metadata, now + ttl);
+                }
+            }
+            if let Some((mac, metadata)) = station {
+                insert_station(&mut set, mac, metadata, now + ttl);
+            }
+        }
+        PortDataFormat::HostApdJson => {
+            let stations: Vec<Station> = serde_json::from_str(data).map_err(Error::ParseError)?;
+            for station in stations {
+                let metadata = Metadata {
+                    signal: station.signal,
+                    connected_secs: station.connected_time,
+                };
+                insert_station(&mut set, station.mac, metadata, now + ttl);
+            }
+        }
+        PortDataFormat::Custom(name) => {
+            let parser = context
+                .formats
+                .port
+                .get(name)
+                .ok_or_else(|| Error::UnknownFormat(name.clone()))?;
+            set = parser(data)?;
+        }
     }
 
     Ok(set)
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum PortDataFormat {
     #[serde(rename = "hostapd")]
     HostApd,
+    #[serde(rename = "hostapd-json")]
+    HostApdJson,
+    #[serde(rename = "custom")]
+    Custom(String),
 }
 
-#[derive(Deserialize)]
+impl PortDataFormat {
+    pub fn source(&self) -> Source {
+        match self {
+            PortDataFormat::HostApd | PortDataFormat::HostApdJson => Source::Wireless,
+            PortDataFormat::Custom(name) => Source::Custom(name.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum PortPoller {
     File {
         file: String,
         format: PortDataFormat,
+        #[serde(default)]
+        tail: bool,
+        /// How long what the file lists stays visible.
+        #[serde(default)]
+        expiry_secs: Option<u64>,
+        /// How long reading the file may take, defaults to 5 seconds.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        format: PortDataFormat,
+        /// How long the command may run before it is killed, defaults to 5
+        /// seconds.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    Stdin {
+        format: PortDataFormat,
+    },
+    #[cfg(feature = "http")]
+    Http {
+        url: String,
+        format: PortDataFormat,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    #[cfg(feature = "pcap")]
+    Pcap {
+        interface: String,
+        duration_secs: u64,
     },
 }
 
 impl PortPoller {
-    pub fn poll(&self, root: &Path) -> Result<ExpireSet<MacAddress>, Error> {
-        let (data, format) = match self {
-            PortPoller::File { file, format } => {
-                let path = root.join(file);
+    pub fn source(&self) -> Source {
+        match self {
+            PortPoller::File { format, .. }
+            | PortPoller::Command { format, .. }
+            | PortPoller::Stdin { format } => format.source(),
+            #[cfg(feature = "http")]
+            PortPoller::Http { format, .. } => format.source(),
+            #[cfg(feature = "pcap")]
+            PortPoller::Pcap { .. } => Source::Capture,
+        }
+    }
+
+    /// How long the poller may take, when its config says.
+    pub fn timeout_secs(&self) -> Option<u64> {
+        match self {
+            PortPoller::File { timeout_secs, .. } | PortPoller::Command { timeout_secs, .. } => {
+                *timeout_secs
+            }
+            #[cfg(feature = "http")]
+            PortPoller::Http { timeout_secs, .. } => *timeout_secs,
+            _ => None,
+        }
+    }
 
-                let mut file = File::open(path).map_err(Error::IoError)?;
-                let mut data = String::new();
-                file.read_to_string(&mut data).map_err(Error::IoError)?;
-                (data, *format)
+    pub fn poll(&self, context: &Context) -> Result<ExpireSet<MacAddress>, Error> {
+        let (data, format, ttl) = match self {
+            PortPoller::File {
+                file,
+                format,
+                tail,
+                expiry_secs,
+                timeout_secs,
+            } => {
+                let data = read_file(
+                    context.root.join(file),
+                    *tail,
+                    io_timeout(*timeout_secs),
+                    context,
+                )?;
+                (data, format, expiry(*expiry_secs, context.default_expiry))
+            }
+            PortPoller::Command {
+                command,
+                args,
+                format,
+                timeout_secs,
+            } => {
+                let data =
+                    crate::command::run(command, args, context.root, io_timeout(*timeout_secs))?;
+                (data, format, context.default_expiry)
+            }
+            PortPoller::Stdin { format } => (read_stdin(context)?, format, context.default_expiry),
+            #[cfg(feature = "http")]
+            PortPoller::Http {
+                url,
+                format,
+                headers,
+                timeout_secs,
+            } => {
+                let data = crate::http::get(url, headers, http_timeout(*timeout_secs))?;
+                (data, format, context.default_expiry)
+            }
+            #[cfg(feature = "pcap")]
+            PortPoller::Pcap {
+                interface,
+                duration_secs,
+            } => {
+                let macs = crate::capture::listen(interface, Duration::from_secs(*duration_secs))?;
+                let now = context.now();
+                let mut set = ExpireSet::default();
+                for mac in macs.into_iter().filter(|m| is_valid_mac(*m)) {
+                    set.insert_at(mac, now, context.default_expiry);
+                }
+                return Ok(set);
             }
         };
 
-        parse_port_data(data, format)
+        parse_port_data(&data, format, ttl, context)
+    }
+
+    /// Polls without blocking on files and commands.
+    #[cfg(feature = "async")]
+    pub async fn poll_async(&self, context: &Context<'_>) -> Result<ExpireSet<MacAddress>, Error> {
+        match self {
+            PortPoller::File {
+                file,
+                format,
+                tail: false,
+                expiry_secs,
+                ..
+            } => {
+                let data = crate::nonblocking::read_file(&context.root.join(file)).await?;
+                parse_port_data(
+                    &data,
+                    format,
+                    expiry(*expiry_secs, context.default_expiry),
+                    context,
+                )
+            }
+            PortPoller::Command {
+                command,
+                args,
+                format,
+                ..
+            } => {
+                let data = crate::nonblocking::run(command, args, context.root).await?;
+                parse_port_data(&data, format, context.default_expiry, context)
+            }
+            _ => self.poll(context),
+        }
+    }
+
+    /// Parses data as if this poller had read it.
+    pub fn parse(&self, data: &str) -> Result<ExpireSet<MacAddress>, Error> {
+        match self {
+            PortPoller::File {
+                format,
+                expiry_secs,
+                ..
+            } => Context::detached(|context| {
+                parse_port_data(data, format, expiry(*expiry_secs, DEFAULT_EXPIRY), context)
+            }),
+            PortPoller::Command { format, .. } | PortPoller::Stdin { format } => {
+                Context::detached(|context| parse_port_data(data, format, DEFAULT_EXPIRY, context))
+            }
+            #[cfg(feature = "http")]
+            PortPoller::Http { format, .. } => {
+                Context::detached(|context| parse_port_data(data, format, DEFAULT_EXPIRY, context))
+            }
+            #[cfg(feature = "pcap")]
+            PortPoller::Pcap { .. } => Err(Error::UnknownFormat("pcap".to_owned())),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DeviceData {
+    pub ports: HashMap<String, ExpireSet<MacAddress>>,
+    /// Entries for the network's own infrastructure rather than its clients.
+    pub infrastructure: HashMap<String, ExpireSet<MacAddress>>,
+    pub macs: HashSet<MacAddress>,
+    /// The addresses neighbour tables associate with hardware.
+    pub addresses: HashMap<MacAddress, Vec<IpAddr>>,
+    /// Where the data came from when that was only known once it was parsed.
+    pub source: Option<Source>,
+    /// The chassis of the neighbours announcing themselves on each port.
+    pub neighbours: HashMap<String, ExpireSet<MacAddress>>,
+    /// The ports neighbours announced themselves from.
+    pub remote_ports: HashMap<MacAddress, String>,
+    /// The VLAN each port's hardware was learned on.
+    pub vlans: HashMap<String, HashMap<MacAddress, u16>>,
+    /// The bridge each port is a member of.
+    pub masters: HashMap<String, String>,
+}
+
+impl DeviceData {
+    fn add_address(&mut self, mac: MacAddress, address: IpAddr) {
+        let addresses = self.addresses.entry(mac).or_default();
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+}
+
+/// Controls how a device poller interprets what it reads.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DeviceDataOptions {
+    #[serde(default)]
+    pub learn_self_macs: bool,
+    /// Forwarding database entries idle for longer than this are ignored.
+    #[serde(default)]
+    pub max_idle_secs: Option<u64>,
+    /// Forwarding database entries with any of these flags are infrastructure
+    /// rather than clients.
+    #[serde(default)]
+    pub infrastructure_flags: Vec<String>,
+    /// Stops parsing after this many lines.
+    #[serde(default)]
+    pub max_lines: Option<usize>,
+    /// Ignores forwarding database entries on VLAN sub-interfaces like
+    /// `eth0.100`.
+    #[serde(default)]
+    pub skip_vlan_interfaces: bool,
+}
+
+/// Guesses the format of some device data from the shape of its first line.
+pub fn detect_format(data: &str) -> Option<DeviceDataFormat> {
+    let line = data.lines().find(|line| !line.trim().is_empty())?;
+    let parts = tokenize_line(line);
+
+    if line.starts_with("lldp.") {
+        Some(DeviceDataFormat::Lldp)
+    } else if parts.first() == Some(&"Port") {
+        Some(DeviceDataFormat::SwConfig)
+    } else if parts.starts_with(&["IP", "address"]) {
+        Some(DeviceDataFormat::ProcArp)
+    } else if parts.contains(&"lladdr") && parts.contains(&"dev") {
+        Some(DeviceDataFormat::IpNeigh)
+    } else if parts.contains(&"dev") {
+        Some(DeviceDataFormat::ForwardDb)
+    } else {
+        None
     }
 }
 
 fn parse_device_data(
-    data: String,
-    format: DeviceDataFormat,
-) -> Result<HashMap<String, ExpireSet<MacAddress>>, Error> {
-    let expiry = Instant::now() + Duration::from_secs(5);
-    let mut map: HashMap<String, ExpireSet<MacAddress>> = HashMap::new();
+    data: &str,
+    format: &DeviceDataFormat,
+    options: &DeviceDataOptions,
+    ttl: Duration,
+    context: &Context,
+) -> Result<DeviceData, Error> {
+    // Bound the work a runaway device can cause.
+    let mut data = data;
+    if let Some(max_lines) = options.max_lines {
+        let end: usize = data
+            .split_inclusive('\n')
+            .take(max_lines)
+            .map(str::len)
+            .sum();
+        if end < data.len() {
+            log::warn!("device data truncated after {} lines.", max_lines);
+            data = &data[..end];
+        }
+    }
+
+    let learn_self_macs = options.learn_self_macs;
+    let max_idle = options.max_idle_secs.map(Duration::from_secs);
+    let now = context.now();
+    let mut result = DeviceData::default();
 
     match format {
+        DeviceDataFormat::Auto => {
+            if data.trim().is_empty() {
+                return Ok(result);
+            }
+
+            let detected =
+                detect_format(data).ok_or_else(|| Error::UnknownFormat("auto".to_owned()))?;
+            log::trace!("detected device data format {:?}.", detected);
+            let mut result = parse_device_data(data, &detected, options, ttl, context)?;
+            result.source = Some(detected.source());
+            return Ok(result);
+        }
         DeviceDataFormat::ForwardDb => {
+            // Entries name the bridge a port belongs to as `master <bridge>`,
+            // bridges may themselves be members of other bridges.
+            for line in data.split('\n') {
+                let parts = tokenize_line(line);
+                let port = parts
+                    .iter()
+                    .position(|p| *p == "dev")
+                    .and_then(|i| parts.get(i + 1));
+                let master = parts
+                    .iter()
+                    .position(|p| *p == "master")
+                    .and_then(|i| parts.get(i + 1));
+                if let (Some(port), Some(master)) = (port, master) {
+                    if port != master {
+                        result
+                            .masters
+                            .insert((*port).to_owned(), (*master).to_owned());
+                    }
+                }
+            }
+
             for line in data.split('\n') {
-                let mut parts = line.split(' ');
+                let mut parts = tokenize_line(line).into_iter();
 
                 let addr = unwrap_option_or_continue!(parts.next());
-                let mac = unwrap_result_or_continue!(MacAddress::from_str(addr));
+                let mac = unwrap_option_or_continue!(parse_mac_token(addr));
                 if !is_valid_mac(mac) {
                     continue;
                 }
@@ -113,89 +811,920 @@ fn parse_device_data(
                 }
 
                 let port = unwrap_option_or_continue!(parts.next());
-                let flags: HashSet<&str> = parts.collect();
+                if options.skip_vlan_interfaces && is_vlan_interface(port) {
+                    continue;
+                }
+
+                let parts: Vec<&str> = parts.collect();
+                let flags: HashSet<&str> = parts.iter().copied().collect();
+                let vlan = parts
+                    .iter()
+                    .position(|p| *p == "vlan")
+                    .and_then(|i| parts.get(i + 1))
+                    .and_then(|vlan| vlan.parse::<u16>().ok());
+                if flags.contains("self") {
+                    if learn_self_macs {
+                        log::trace!("fdb reported own hardware {}", mac);
+                        result.macs.insert(mac);
+                    }
+                    continue;
+                }
+
                 if flags.contains("permanent") {
                     continue;
                 }
 
+                // Statistics include how long ago the entry was last used as
+                // `used <idle>/<updated>`, entries idle for too long are
+                // ignored and the rest expire sooner the longer they've idled.
+                let mut ttl = ttl;
+                let idle = parts
+                    .iter()
+                    .position(|p| *p == "used")
+                    .and_then(|i| parts.get(i + 1))
+                    .and_then(|used| used.split('/').next())
+                    .and_then(|idle| idle.parse::<u64>().ok())
+                    .map(Duration::from_secs);
+                if let (Some(idle), Some(max_idle)) = (idle, max_idle) {
+                    if idle >= max_idle {
+                        log::trace!("fdb reported stale hardware {}", mac);
+                        continue;
+                    }
+
+                    ttl = ttl.mul_f64(1.0 - idle.as_secs_f64() / max_idle.as_secs_f64());
+                }
+
+                // Hardware learned by a nested bridge is also reported on the
+                // bridge itself, the member port it was learned on is nearer.
+                let nested =
+                    result
+                        .ports
+                        .iter()
+                        .chain(result.infrastructure.iter())
+                        .any(|(other, set)| {
+                            set.contains(&mac) && is_bridge_member(&result.masters, other, port)
+                        });
+                if nested {
+                    log::trace!("fdb reported hardware {} behind bridge {}", mac, port);
+                    continue;
+                }
+
                 log::trace!("fdb reported hardware {}", mac);
 
-                if let Some(set) = map.get_mut(port) {
-                    set.insert(mac, expiry);
+                // Hardware can only be on one port of each VLAN, the last
+                // entry wins.
+                for (other, set) in result
+                    .ports
+                    .iter_mut()
+                    .chain(result.infrastructure.iter_mut())
+                {
+                    let other_vlan = result.vlans.get(other).and_then(|v| v.get(&mac));
+                    if other_vlan.copied() == vlan || is_bridge_member(&result.masters, port, other)
+                    {
+                        set.remove(&mac);
+                    }
+                }
+                if let Some(vlan) = vlan {
+                    result
+                        .vlans
+                        .entry(port.to_owned())
+                        .or_default()
+                        .insert(mac, vlan);
+                }
+
+                let infrastructure = options
+                    .infrastructure_flags
+                    .iter()
+                    .any(|flag| flags.contains(flag.as_str()));
+                let bucket = if infrastructure {
+                    &mut result.infrastructure
+                } else {
+                    &mut result.ports
+                };
+
+                if let Some(set) = bucket.get_mut(port) {
+                    set.insert_at(mac, now, ttl);
                 } else {
                     let mut set = ExpireSet::default();
-                    set.insert(mac, expiry);
-                    map.insert(port.to_owned(), set);
+                    set.insert_at(mac, now, ttl);
+                    bucket.insert(port.to_owned(), set);
                 }
             }
         }
         DeviceDataFormat::SwConfig => {
+            // Some builds list each MAC on an indented line beneath a port header.
+            let mut current_ports: Option<Vec<String>> = None;
+
             for line in data.split('\n') {
-                let mut parts = line.split(' ');
+                let indented = line.starts_with(char::is_whitespace);
+                let mut parts = tokenize_line(line).into_iter();
 
-                if parts.next() != Some("Port") {
-                    log::warn!("swconfig line appears invalid, missing port.");
+                if !indented {
+                    current_ports = None;
+                    if parts.next() != Some("Port") {
+                        log::warn!("swconfig line appears invalid, missing port.");
+                        continue;
+                    }
+
+                    let spec = unwrap_option_or_continue!(parts.next()).trim_end_matches(':');
+                    match expand_port_spec(spec) {
+                        Some(ports) => current_ports = Some(ports),
+                        None => {
+                            log::warn!("swconfig port `{}` appears invalid.", spec);
+                            continue;
+                        }
+                    }
+                }
+                let ports = unwrap_option_or_continue!(current_ports.as_ref());
+
+                let mut parts = parts.peekable();
+                if parts.peek().is_none() {
                     continue;
                 }
 
-                let port = unwrap_option_or_continue!(parts.next()).trim_end_matches(':');
+                while let Some(part) = parts.next() {
+                    if part != "MAC" {
+                        log::warn!("swconfig line appears invalid, missing mac.");
+                        break;
+                    }
+
+                    let addr = unwrap_option_or_continue!(parts.next());
+                    let mac = unwrap_option_or_continue!(parse_mac_token(addr));
+                    if !is_valid_mac(mac) {
+                        continue;
+                    }
+
+                    log::trace!("swconfig reported hardware {}", mac);
 
-                if parts.next() != Some("MAC") {
-                    log::warn!("swconfig line appears invalid, missing mac.");
+                    for port in ports {
+                        if let Some(set) = result.ports.get_mut(port) {
+                            set.insert_at(mac, now, ttl);
+                        } else {
+                            let mut set = ExpireSet::default();
+                            set.insert_at(mac, now, ttl);
+                            result.ports.insert(port.to_owned(), set);
+                        }
+                    }
+                }
+            }
+        }
+        DeviceDataFormat::ProcArp => {
+            // The first line is a header.
+            for line in data.split('\n').skip(1) {
+                let parts = tokenize_line(line);
+                if parts.len() < 6 {
                     continue;
                 }
 
-                let addr = unwrap_option_or_continue!(parts.next());
-                let mac = unwrap_result_or_continue!(MacAddress::from_str(addr));
+                if parts[2] == "0x0" {
+                    continue;
+                }
+
+                let mac = unwrap_option_or_continue!(parse_mac_token(parts[3]));
                 if !is_valid_mac(mac) {
                     continue;
                 }
 
-                log::trace!("swconfig reported hardware {}", mac);
+                let port = parts[5];
 
-                if let Some(set) = map.get_mut(port) {
-                    set.insert(mac, expiry);
+                log::trace!("arp reported hardware {}", mac);
+                if let Ok(address) = IpAddr::from_str(parts[0]) {
+                    result.add_address(mac, address);
+                }
+
+                if let Some(set) = result.ports.get_mut(port) {
+                    set.insert_at(mac, now, ttl);
                 } else {
                     let mut set = ExpireSet::default();
-                    set.insert(mac, expiry);
-                    map.insert(port.to_owned(), set);
+                    set.insert_at(mac, now, ttl);
+                    result.ports.insert(port.to_owned(), set);
                 }
             }
         }
+        DeviceDataFormat::IpNeigh => {
+            // Lines look like `<address> dev <port> lladdr <mac> <state>`,
+            // covering both ARP and NDP entries so `ip -6 neigh` works too.
+            for line in data.split('\n') {
+                let parts = tokenize_line(line);
+                let address = unwrap_result_or_continue!(IpAddr::from_str(
+                    unwrap_option_or_continue!(parts.first())
+                ));
+
+                let field = |name: &str| {
+                    parts
+                        .iter()
+                        .position(|p| *p == name)
+                        .and_then(|i| parts.get(i + 1))
+                };
+
+                let port = unwrap_option_or_continue!(field("dev"));
+                let addr = unwrap_option_or_continue!(field("lladdr"));
+                let mac = unwrap_option_or_continue!(parse_mac_token(addr));
+                if !is_valid_mac(mac) || parts.contains(&"FAILED") || parts.contains(&"INCOMPLETE")
+                {
+                    continue;
+                }
+
+                log::trace!("neighbours reported hardware {}", mac);
+                result.add_address(mac, address);
+
+                if let Some(set) = result.ports.get_mut(*port) {
+                    set.insert_at(mac, now, ttl);
+                } else {
+                    let mut set = ExpireSet::default();
+                    set.insert_at(mac, now, ttl);
+                    result.ports.insert((*port).to_owned(), set);
+                }
+            }
+        }
+        DeviceDataFormat::Lldp => {
+            // `lldpctl -f keyvalue` prints lines like
+            // `lldp.<port>.chassis.mac=<mac>` for each neighbour.
+            let mut chassis: HashMap<&str, MacAddress> = HashMap::new();
+            let mut remote_ports: HashMap<&str, &str> = HashMap::new();
+
+            for line in data.split('\n') {
+                let (key, value) = unwrap_option_or_continue!(line.trim().split_once('='));
+                let key = unwrap_option_or_continue!(key.strip_prefix("lldp."));
+
+                if let Some(port) = key.strip_suffix(".chassis.mac") {
+                    let mac = unwrap_option_or_continue!(parse_mac_token(value));
+                    if is_valid_mac(mac) {
+                        chassis.insert(port, mac);
+                    }
+                } else if let Some(port) = key
+                    .strip_suffix(".port.ifname")
+                    .or_else(|| key.strip_suffix(".port.local"))
+                {
+                    remote_ports.insert(port, value);
+                }
+            }
+
+            for (port, mac) in chassis {
+                log::trace!("lldp reported neighbour {}", mac);
+
+                if let Some(remote_port) = remote_ports.get(port) {
+                    result.remote_ports.insert(mac, (*remote_port).to_owned());
+                }
+
+                result
+                    .neighbours
+                    .entry(port.to_owned())
+                    .or_default()
+                    .insert_at(mac, now, ttl);
+            }
+        }
+        DeviceDataFormat::Custom(name) => {
+            let parser = context
+                .formats
+                .device
+                .get(name)
+                .ok_or_else(|| Error::UnknownFormat(name.clone()))?;
+            result.ports = parser(data)?;
+        }
     }
-    Ok(map)
+    Ok(result)
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum DeviceDataFormat {
+    #[serde(rename = "auto")]
+    Auto,
     #[serde(rename = "fdb")]
     ForwardDb,
     #[serde(rename = "swc")]
     SwConfig,
+    #[serde(rename = "proc-arp")]
+    ProcArp,
+    #[serde(rename = "ip-neigh", alias = "arp")]
+    IpNeigh,
+    #[serde(rename = "lldp")]
+    Lldp,
+    #[serde(rename = "custom")]
+    Custom(String),
 }
 
-#[derive(Deserialize)]
+impl DeviceDataFormat {
+    pub fn source(&self) -> Source {
+        match self {
+            DeviceDataFormat::ForwardDb | DeviceDataFormat::SwConfig => Source::ForwardDb,
+            // Until the data says otherwise it is trusted the least.
+            DeviceDataFormat::ProcArp | DeviceDataFormat::IpNeigh | DeviceDataFormat::Auto => {
+                Source::Arp
+            }
+            DeviceDataFormat::Lldp => Source::Lldp,
+            DeviceDataFormat::Custom(name) => Source::Custom(name.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum DevicePoller {
     File {
         file: String,
         format: DeviceDataFormat,
+        #[serde(default)]
+        tail: bool,
+        /// How long what the file lists stays visible.
+        #[serde(default)]
+        expiry_secs: Option<u64>,
+        /// How long reading the file may take, defaults to 5 seconds.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
+    },
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        format: DeviceDataFormat,
+        /// How long the command may run before it is killed, defaults to 5
+        /// seconds.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
+    },
+    Stdin {
+        format: DeviceDataFormat,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
+    },
+    #[cfg(feature = "http")]
+    Http {
+        url: String,
+        format: DeviceDataFormat,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
+    },
+    /// Walks the bridge forwarding table over SNMP.
+    #[cfg(feature = "snmp")]
+    Snmp {
+        host: String,
+        community: String,
+        #[serde(default)]
+        version: Option<String>,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
+    },
+    #[cfg(feature = "ssh")]
+    Ssh {
+        host: String,
+        user: String,
+        key_path: String,
+        command: String,
+        format: DeviceDataFormat,
+        #[serde(flatten)]
+        options: DeviceDataOptions,
     },
 }
 
 impl DevicePoller {
-    pub fn poll(&self, root: &Path) -> Result<HashMap<String, ExpireSet<MacAddress>>, Error> {
-        let (data, format) = match self {
-            DevicePoller::File { file, format } => {
-                let path = root.join(file);
+    pub fn source(&self) -> Source {
+        match self {
+            DevicePoller::File { format, .. }
+            | DevicePoller::Command { format, .. }
+            | DevicePoller::Stdin { format, .. } => format.source(),
+            #[cfg(feature = "http")]
+            DevicePoller::Http { format, .. } => format.source(),
+            #[cfg(feature = "snmp")]
+            DevicePoller::Snmp { .. } => Source::ForwardDb,
+            #[cfg(feature = "ssh")]
+            DevicePoller::Ssh { format, .. } => format.source(),
+        }
+    }
+
+    /// How long the poller may take, when its config says.
+    pub fn timeout_secs(&self) -> Option<u64> {
+        match self {
+            DevicePoller::File { timeout_secs, .. }
+            | DevicePoller::Command { timeout_secs, .. } => *timeout_secs,
+            #[cfg(feature = "http")]
+            DevicePoller::Http { timeout_secs, .. } => *timeout_secs,
+            _ => None,
+        }
+    }
+
+    pub fn poll(&self, context: &Context) -> Result<DeviceData, Error> {
+        let (data, format, options, ttl) = match self {
+            DevicePoller::File {
+                file,
+                format,
+                tail,
+                expiry_secs,
+                timeout_secs,
+                options,
+            } => {
+                let data = read_file(
+                    context.root.join(file),
+                    *tail,
+                    io_timeout(*timeout_secs),
+                    context,
+                )?;
+                (
+                    data,
+                    format,
+                    options,
+                    expiry(*expiry_secs, context.default_expiry),
+                )
+            }
+            DevicePoller::Command {
+                command,
+                args,
+                format,
+                timeout_secs,
+                options,
+            } => {
+                let data =
+                    crate::command::run(command, args, context.root, io_timeout(*timeout_secs))?;
+                (data, format, options, context.default_expiry)
+            }
+            DevicePoller::Stdin { format, options } => (
+                read_stdin(context)?,
+                format,
+                options,
+                context.default_expiry,
+            ),
+            #[cfg(feature = "http")]
+            DevicePoller::Http {
+                url,
+                format,
+                headers,
+                timeout_secs,
+                options,
+            } => {
+                let data = crate::http::get(url, headers, http_timeout(*timeout_secs))?;
+                (data, format, options, context.default_expiry)
+            }
+            #[cfg(feature = "snmp")]
+            DevicePoller::Snmp {
+                host,
+                community,
+                version,
+                options,
+            } => {
+                let entries = crate::snmp::forwarding_table(
+                    host,
+                    community,
+                    version.as_deref().unwrap_or("2c"),
+                )?;
+                let now = context.now();
+                let mut result = DeviceData::default();
+                for (port, mac, own) in entries {
+                    if !is_valid_mac(mac) {
+                        continue;
+                    }
+
+                    if own {
+                        if options.learn_self_macs {
+                            result.macs.insert(mac);
+                        }
+                        continue;
+                    }
 
-                let mut file = File::open(path).map_err(Error::IoError)?;
-                let mut data = String::new();
-                file.read_to_string(&mut data).map_err(Error::IoError)?;
-                (data, *format)
+                    result.ports.entry(port).or_default().insert_at(
+                        mac,
+                        now,
+                        context.default_expiry,
+                    );
+                }
+                return Ok(result);
+            }
+            #[cfg(feature = "ssh")]
+            DevicePoller::Ssh {
+                host,
+                user,
+                key_path,
+                command,
+                format,
+                options,
+            } => {
+                let data = crate::ssh::run(host, user, &context.root.join(key_path), command)?;
+                (data, format, options, context.default_expiry)
             }
         };
 
-        parse_device_data(data, format)
+        parse_device_data(&data, format, options, ttl, context)
+    }
+
+    /// Polls without blocking on files and commands.
+    #[cfg(feature = "async")]
+    pub async fn poll_async(&self, context: &Context<'_>) -> Result<DeviceData, Error> {
+        match self {
+            DevicePoller::File {
+                file,
+                format,
+                tail: false,
+                expiry_secs,
+                options,
+                ..
+            } => {
+                let data = crate::nonblocking::read_file(&context.root.join(file)).await?;
+                parse_device_data(
+                    &data,
+                    format,
+                    options,
+                    expiry(*expiry_secs, context.default_expiry),
+                    context,
+                )
+            }
+            DevicePoller::Command {
+                command,
+                args,
+                format,
+                options,
+                ..
+            } => {
+                let data = crate::nonblocking::run(command, args, context.root).await?;
+                parse_device_data(&data, format, options, context.default_expiry, context)
+            }
+            _ => self.poll(context),
+        }
+    }
+
+    /// Parses data as if this poller had read it.
+    pub fn parse(&self, data: &str) -> Result<DeviceData, Error> {
+        let (format, options, ttl) = match self {
+            DevicePoller::File {
+                format,
+                expiry_secs,
+                options,
+                ..
+            } => (format, options, expiry(*expiry_secs, DEFAULT_EXPIRY)),
+            DevicePoller::Command {
+                format, options, ..
+            }
+            | DevicePoller::Stdin { format, options } => (format, options, DEFAULT_EXPIRY),
+            #[cfg(feature = "http")]
+            DevicePoller::Http {
+                format, options, ..
+            } => (format, options, DEFAULT_EXPIRY),
+            #[cfg(feature = "snmp")]
+            DevicePoller::Snmp { .. } => return Err(Error::UnknownFormat("snmp".to_owned())),
+            #[cfg(feature = "ssh")]
+            DevicePoller::Ssh {
+                format, options, ..
+            } => (format, options, DEFAULT_EXPIRY),
+        };
+
+        Context::detached(|context| parse_device_data(data, format, options, ttl, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac(mac: &str) -> MacAddress {
+        MacAddress::parse_str(mac).unwrap()
+    }
+
+    /// Parses data with a device poller described in JSON.
+    fn parse(poller: &str, data: &str) -> DeviceData {
+        let poller: DevicePoller = serde_json::from_str(poller).unwrap();
+        poller.parse(data).unwrap()
+    }
+
+    fn macs(set: &ExpireSet<MacAddress>) -> Vec<MacAddress> {
+        let mut macs: Vec<MacAddress> = set.iter().copied().collect();
+        macs.sort();
+        macs
+    }
+
+    #[test]
+    fn tokenize_line_skips_repeated_whitespace() {
+        assert_eq!(
+            tokenize_line("  00:11:22:33:44:55 \tdev  eth0\n"),
+            vec!["00:11:22:33:44:55", "dev", "eth0"]
+        );
+        assert!(tokenize_line(" \t ").is_empty());
+    }
+
+    #[test]
+    fn parse_mac_token_accepts_common_forms() {
+        let expected = mac("00:1a:2b:3c:4d:5e");
+        assert_eq!(parse_mac_token("00:1a:2b:3c:4d:5e"), Some(expected));
+        assert_eq!(parse_mac_token("00-1A-2B-3C-4D-5E"), Some(expected));
+        assert_eq!(parse_mac_token("001a.2b3c.4d5e"), Some(expected));
+    }
+
+    #[test]
+    fn parse_mac_token_rejects_invalid_tokens() {
+        for token in [
+            "",
+            "dev",
+            "00:1a:2b:3c:4d",
+            "00:1a:2b:3c:4d:5e:6f",
+            "00:1a:2b:3c:4d:5g",
+            "0:1a:2b:3c:4d:5e",
+            "001a.2b3c",
+            "001a.2b3c.4d5",
+            "+01a.2b3c.4d5e",
+        ] {
+            assert_eq!(parse_mac_token(token), None, "{}", token);
+        }
+    }
+
+    #[test]
+    fn grouped_swconfig_attributes_macs_to_their_port() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "swc"}"#,
+            "Port 1: MAC 00:11:22:33:44:0a MAC 00:11:22:33:44:0b\n\
+             Port 2:\n\
+             \tMAC 00:11:22:33:44:0c\n\
+             \tMAC 00:11:22:33:44:0d\n\
+             Port 3:\n",
+        );
+
+        assert_eq!(
+            macs(&data.ports["1"]),
+            vec![mac("00:11:22:33:44:0a"), mac("00:11:22:33:44:0b")]
+        );
+        assert_eq!(
+            macs(&data.ports["2"]),
+            vec![mac("00:11:22:33:44:0c"), mac("00:11:22:33:44:0d")]
+        );
+        assert!(!data.ports.contains_key("3"));
+    }
+
+    #[test]
+    fn proc_arp_skips_the_header_and_incomplete_entries() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "proc-arp"}"#,
+            "IP address       HW type     Flags       HW address            Mask     Device\n\
+             192.168.1.5      0x1         0x2         00:11:22:33:44:05     *        eth0\n\
+             192.168.1.6      0x1         0x0         00:00:00:00:00:00     *        eth0\n\
+             192.168.1.7      0x1         0x2         00:11:22:33:44:07     *        eth1\n",
+        );
+
+        assert_eq!(data.ports.len(), 2);
+        assert_eq!(macs(&data.ports["eth0"]), vec![mac("00:11:22:33:44:05")]);
+        assert_eq!(macs(&data.ports["eth1"]), vec![mac("00:11:22:33:44:07")]);
+        assert_eq!(
+            data.addresses[&mac("00:11:22:33:44:05")],
+            vec![IpAddr::from_str("192.168.1.5").unwrap()]
+        );
+        assert_eq!(data.addresses.len(), 2);
+    }
+
+    #[test]
+    fn hostapd_json_stations_are_parsed() {
+        let poller: PortPoller =
+            serde_json::from_str(r#"{"type": "stdin", "format": "hostapd-json"}"#).unwrap();
+        let set = poller
+            .parse(
+                r#"[
+                    {"mac": "00:11:22:33:44:0a", "connected_time": 3600, "signal": -52,
+                     "rx_bytes": 1024, "flags": "[AUTH][ASSOC][AUTHORIZED]"},
+                    {"mac": "00:11:22:33:44:0b"}
+                ]"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            macs(&set),
+            vec![mac("00:11:22:33:44:0a"), mac("00:11:22:33:44:0b")]
+        );
+        assert_eq!(
+            set.metadata(&mac("00:11:22:33:44:0a")),
+            Some(&Metadata {
+                signal: Some(-52),
+                connected_secs: Some(3600),
+            })
+        );
+    }
+
+    #[test]
+    fn moved_hardware_lands_on_the_last_port() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "fdb"}"#,
+            "00:11:22:33:44:02 dev lan1\n\
+             00:11:22:33:44:03 dev lan1\n\
+             00:11:22:33:44:02 dev lan2\n",
+        );
+
+        assert_eq!(macs(&data.ports["lan1"]), vec![mac("00:11:22:33:44:03")]);
+        assert_eq!(macs(&data.ports["lan2"]), vec![mac("00:11:22:33:44:02")]);
+    }
+
+    #[test]
+    fn idle_fdb_entries_expire_sooner() {
+        let data = parse(
+            r#"{"type": "file", "file": "sw.fdb", "format": "fdb", "expiry_secs": 60, "max_idle_secs": 60}"#,
+            "00:11:22:33:44:02 dev lan1 used 0/20 master br0\n\
+             00:11:22:33:44:03 dev lan1 used 30/200 master br0\n\
+             00:11:22:33:44:04 dev lan1 used 100/200 master br0\n",
+        );
+
+        let set = &data.ports["lan1"];
+        assert_eq!(
+            macs(set),
+            vec![mac("00:11:22:33:44:02"), mac("00:11:22:33:44:03")]
+        );
+        let now = Instant::now();
+        let fresh = set.remaining(&mac("00:11:22:33:44:02"), now).unwrap();
+        let idle = set.remaining(&mac("00:11:22:33:44:03"), now).unwrap();
+        assert!(fresh > Duration::from_secs(55));
+        assert!(idle <= Duration::from_secs(30) && idle > Duration::from_secs(25));
+    }
+
+    #[test]
+    fn flagged_fdb_entries_are_infrastructure() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "fdb", "infrastructure_flags": ["master"]}"#,
+            "00:11:22:33:44:21 dev lan1 master br0\n\
+             00:11:22:33:44:22 dev lan1\n\
+             00:11:22:33:44:23 dev lan1 extern_learn\n",
+        );
+
+        assert_eq!(
+            macs(&data.infrastructure["lan1"]),
+            vec![mac("00:11:22:33:44:21")]
+        );
+        assert_eq!(
+            macs(&data.ports["lan1"]),
+            vec![mac("00:11:22:33:44:22"), mac("00:11:22:33:44:23")]
+        );
+    }
+
+    #[test]
+    fn file_pollers_parse_without_reading_files() {
+        let data = parse(
+            r#"{"type": "file", "file": "does/not/exist.fdb", "format": "fdb", "expiry_secs": 60}"#,
+            "00:11:22:33:44:02 dev lan1\n",
+        );
+
+        assert_eq!(data.ports.len(), 1);
+        assert_eq!(macs(&data.ports["lan1"]), vec![mac("00:11:22:33:44:02")]);
+        assert!(
+            data.ports["lan1"]
+                .remaining(&mac("00:11:22:33:44:02"), Instant::now())
+                .unwrap()
+                > Duration::from_secs(55)
+        );
+    }
+
+    /// Collects the warnings logged by every test.
+    struct Warnings(std::sync::Mutex<Vec<String>>);
+
+    impl log::Log for Warnings {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static WARNINGS: Warnings = Warnings(std::sync::Mutex::new(Vec::new()));
+
+    fn warned(message: &str) -> bool {
+        let _ = log::set_logger(&WARNINGS);
+        log::set_max_level(log::LevelFilter::Warn);
+        WARNINGS.0.lock().unwrap().iter().any(|w| w == message)
+    }
+
+    #[test]
+    fn long_device_data_is_truncated() {
+        assert!(!warned("device data truncated after 2 lines."));
+        let data = parse(
+            r#"{"type": "stdin", "format": "fdb", "max_lines": 2}"#,
+            "00:11:22:33:44:02 dev lan1\n\
+             00:11:22:33:44:03 dev lan1\n\
+             00:11:22:33:44:04 dev lan1\n",
+        );
+
+        assert_eq!(
+            macs(&data.ports["lan1"]),
+            vec![mac("00:11:22:33:44:02"), mac("00:11:22:33:44:03")]
+        );
+        assert!(warned("device data truncated after 2 lines."));
+    }
+
+    #[test]
+    fn formats_are_detected_from_content() {
+        for (data, format) in [
+            (
+                "00:11:22:33:44:02 dev lan1 master br0\n",
+                Some(DeviceDataFormat::ForwardDb),
+            ),
+            (
+                "\nPort 1: MAC 00:11:22:33:44:02\n",
+                Some(DeviceDataFormat::SwConfig),
+            ),
+            (
+                "IP address       HW type     Flags       HW address            Mask     Device\n",
+                Some(DeviceDataFormat::ProcArp),
+            ),
+            (
+                "192.168.1.5 dev lan lladdr 00:11:22:33:44:02 REACHABLE\n",
+                Some(DeviceDataFormat::IpNeigh),
+            ),
+            (
+                "lldp.eth0.chassis.mac=00:11:22:33:44:02\n",
+                Some(DeviceDataFormat::Lldp),
+            ),
+            ("something else entirely\n", None),
+            ("", None),
+        ] {
+            assert_eq!(detect_format(data), format, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn auto_format_parses_the_detected_format() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "auto"}"#,
+            "192.168.1.5 dev lan lladdr 00:11:22:33:44:02 REACHABLE\n",
+        );
+
+        assert_eq!(data.source, Some(Source::Arp));
+        assert_eq!(macs(&data.ports["lan"]), vec![mac("00:11:22:33:44:02")]);
+    }
+
+    #[test]
+    fn fdb_entries_are_kept_per_vlan() {
+        let fdb = "33:33:00:00:00:01 dev br0 self permanent\n\
+                   00:11:22:33:44:01 dev br0 vlan 1 master br0 permanent\n\
+                   00:11:22:33:44:10 dev lan1 vlan 10 master br0\n\
+                   00:11:22:33:44:10 dev lan2 vlan 20 master br0\n\
+                   00:11:22:33:44:11 dev lan1 vlan 10 master br0\n\
+                   00:11:22:33:44:11 dev lan2 vlan 10 master br0\n\
+                   00:11:22:33:44:12 dev lan2.30 master br0\n";
+        let data = parse(r#"{"type": "stdin", "format": "fdb"}"#, fdb);
+
+        assert_eq!(macs(&data.ports["lan1"]), vec![mac("00:11:22:33:44:10")]);
+        assert_eq!(
+            macs(&data.ports["lan2"]),
+            vec![mac("00:11:22:33:44:10"), mac("00:11:22:33:44:11")]
+        );
+        assert_eq!(data.vlans["lan1"][&mac("00:11:22:33:44:10")], 10);
+        assert_eq!(data.vlans["lan2"][&mac("00:11:22:33:44:10")], 20);
+        assert_eq!(data.vlans["lan2"][&mac("00:11:22:33:44:11")], 10);
+        assert_eq!(macs(&data.ports["lan2.30"]), vec![mac("00:11:22:33:44:12")]);
+
+        let data = parse(
+            r#"{"type": "stdin", "format": "fdb", "skip_vlan_interfaces": true}"#,
+            fdb,
+        );
+        assert!(!data.ports.contains_key("lan2.30"));
+    }
+
+    #[test]
+    fn swconfig_port_lists_and_ranges_are_expanded() {
+        assert!(!warned("swconfig port `4-1` appears invalid."));
+        let data = parse(
+            r#"{"type": "stdin", "format": "swc"}"#,
+            "Port 1,2: MAC 00:11:22:33:44:0a\n\
+             Port 1-4: MAC 00:11:22:33:44:0b\n\
+             Port 1-2,5: MAC 00:11:22:33:44:0c\n\
+             Port 4-1: MAC 00:11:22:33:44:0d\n",
+        );
+
+        let (a, b, c) = (
+            mac("00:11:22:33:44:0a"),
+            mac("00:11:22:33:44:0b"),
+            mac("00:11:22:33:44:0c"),
+        );
+        assert_eq!(macs(&data.ports["1"]), vec![a, b, c]);
+        assert_eq!(macs(&data.ports["2"]), vec![a, b, c]);
+        assert_eq!(macs(&data.ports["3"]), vec![b]);
+        assert_eq!(macs(&data.ports["4"]), vec![b]);
+        assert_eq!(macs(&data.ports["5"]), vec![c]);
+        assert_eq!(data.ports.len(), 5);
+        assert!(warned("swconfig port `4-1` appears invalid."));
+    }
+
+    #[test]
+    fn nested_bridge_hardware_is_on_the_member_port() {
+        let data = parse(
+            r#"{"type": "stdin", "format": "fdb"}"#,
+            "00:11:22:33:44:02 dev eth0 master br0\n\
+             00:11:22:33:44:03 dev br1 master br0\n\
+             00:11:22:33:44:03 dev eth1 master br1\n\
+             00:11:22:33:44:0a dev eth2 master br1\n\
+             00:11:22:33:44:0a dev br1 master br0\n\
+             00:11:22:33:44:0b dev br1 master br0\n",
+        );
+
+        assert_eq!(macs(&data.ports["eth0"]), vec![mac("00:11:22:33:44:02")]);
+        assert_eq!(macs(&data.ports["eth1"]), vec![mac("00:11:22:33:44:03")]);
+        assert_eq!(macs(&data.ports["eth2"]), vec![mac("00:11:22:33:44:0a")]);
+        assert_eq!(macs(&data.ports["br1"]), vec![mac("00:11:22:33:44:0b")]);
+        assert_eq!(data.masters["eth1"], "br1");
+        assert_eq!(data.masters["br1"], "br0");
+        assert!(is_bridge_member(&data.masters, "eth2", "br0"));
+        assert!(!is_bridge_member(&data.masters, "eth0", "br1"));
     }
 }
@@ -0,0 +1,35 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crate::error::Error;
+
+/// Lays out a DOT graph with Graphviz, returning the output in the given format.
+pub fn render(dot: &str, format: &str) -> Result<Vec<u8>, Error> {
+    let mut child = Command::new("dot")
+        .arg(format!("-T{}", format))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::RenderError(
+                "the Graphviz `dot` command was not found, please install Graphviz".to_owned(),
+            ),
+            _ => Error::IoError(e),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(dot.as_bytes()).map_err(Error::IoError)?;
+    }
+
+    let output = child.wait_with_output().map_err(Error::IoError)?;
+    if !output.status.success() {
+        return Err(Error::RenderError(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
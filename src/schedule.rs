@@ -0,0 +1,97 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// Spreads polling of devices evenly across a poll period rather than polling
+/// everything at once.
+pub struct Scheduler {
+    period: Duration,
+    next: Vec<(String, Instant)>,
+}
+
+impl Scheduler {
+    /// Gives each device its own slot in the period starting from `start`,
+    /// jittered within the first half of the slot.
+    pub fn new(devices: Vec<String>, period: Duration, start: Instant) -> Self {
+        let slot = period / devices.len().max(1) as u32;
+
+        let next = devices
+            .into_iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let mut hasher = DefaultHasher::new();
+                id.hash(&mut hasher);
+                let jitter = (slot / 2).mul_f64((hasher.finish() % 1000) as f64 / 1000.0);
+
+                let offset = slot * index as u32 + jitter;
+                (id, start + offset)
+            })
+            .collect();
+
+        Scheduler { period, next }
+    }
+
+    /// The offset into the period that each device is polled at.
+    pub fn phases(&self, start: Instant) -> Vec<(&str, Duration)> {
+        self.next
+            .iter()
+            .map(|(id, next)| (id.as_str(), next.saturating_duration_since(start)))
+            .collect()
+    }
+
+    /// When the next device is due to be polled.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.next.iter().map(|(_id, next)| *next).min()
+    }
+
+    /// Returns the devices that are due to be polled and schedules their next
+    /// poll a period later.
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        let mut due = Vec::new();
+
+        for (id, next) in self.next.iter_mut() {
+            if *next <= now {
+                due.push(id.clone());
+                while *next <= now {
+                    *next += self.period;
+                }
+            }
+        }
+
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Scheduler;
+
+    #[test]
+    fn devices_are_polled_at_distinct_offsets() {
+        let start = Instant::now();
+        let period = Duration::from_secs(30);
+        let devices = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let mut scheduler = Scheduler::new(devices, period, start);
+
+        let phases = scheduler.phases(start);
+        let slot = period / 3;
+        for (index, (_id, offset)) in phases.iter().enumerate() {
+            assert!(*offset >= slot * index as u32);
+            assert!(*offset < slot * index as u32 + slot / 2);
+        }
+
+        // Each device is due once its own slot starts and never before.
+        assert_eq!(scheduler.due(start + Duration::from_secs(5)), vec!["a"]);
+        assert_eq!(scheduler.due(start + Duration::from_secs(15)), vec!["b"]);
+        assert_eq!(scheduler.due(start + Duration::from_secs(25)), vec!["c"]);
+        assert!(scheduler.due(start + Duration::from_secs(29)).is_empty());
+        assert_eq!(
+            scheduler.due(start + period + Duration::from_secs(5)),
+            vec!["a"]
+        );
+    }
+}
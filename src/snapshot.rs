@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use eui48::MacAddress;
+use serde::{Deserialize, Serialize};
+
+use crate::Link;
+
+/// The discovered links and the hardware nearest to each port at a point in
+/// time.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct Snapshot {
+    pub links: Vec<Link>,
+    /// The devices that could see something or were seen by something.
+    pub devices: BTreeSet<String>,
+    pub ports: BTreeMap<String, BTreeMap<String, BTreeSet<MacAddress>>>,
+}
+
+/// Hardware that is nearest to a different port than it was.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub mac: MacAddress,
+    pub from: (String, String),
+    pub to: (String, String),
+}
+
+/// The changes from one snapshot to a later one.
+#[derive(Clone, Default, Debug)]
+pub struct TopologyDiff {
+    pub added_links: Vec<Link>,
+    pub removed_links: Vec<Link>,
+    pub new_devices: Vec<String>,
+    pub dark_devices: Vec<String>,
+    pub moved: Vec<Move>,
+}
+
+impl TopologyDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_links.is_empty()
+            && self.removed_links.is_empty()
+            && self.new_devices.is_empty()
+            && self.dark_devices.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+impl Snapshot {
+    /// Lists what changed between this snapshot and a later one.
+    pub fn diff(&self, other: &Snapshot) -> TopologyDiff {
+        let before_links: HashSet<&Link> = self.links.iter().collect();
+        let after_links: HashSet<&Link> = other.links.iter().collect();
+
+        let before = self.locations();
+        let after = other.locations();
+        let moved = before
+            .iter()
+            .filter_map(|(mac, from)| match after.get(mac) {
+                Some(to) if to != from => Some(Move {
+                    mac: *mac,
+                    from: from.clone(),
+                    to: to.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        TopologyDiff {
+            added_links: other
+                .links
+                .iter()
+                .filter(|l| !before_links.contains(l))
+                .cloned()
+                .collect(),
+            removed_links: self
+                .links
+                .iter()
+                .filter(|l| !after_links.contains(l))
+                .cloned()
+                .collect(),
+            new_devices: other.devices.difference(&self.devices).cloned().collect(),
+            dark_devices: self.devices.difference(&other.devices).cloned().collect(),
+            moved,
+        }
+    }
+
+    /// The port each piece of hardware is nearest to, the port seeing the
+    /// least other hardware.
+    fn locations(&self) -> BTreeMap<MacAddress, (String, String)> {
+        let mut nearest: BTreeMap<MacAddress, (usize, (String, String))> = BTreeMap::new();
+        for (device, ports) in self.ports.iter() {
+            for (port, macs) in ports.iter() {
+                for mac in macs.iter() {
+                    let location = (macs.len(), (device.clone(), port.clone()));
+                    match nearest.get(mac) {
+                        Some((count, _)) if *count <= macs.len() => {}
+                        _ => {
+                            nearest.insert(*mac, location);
+                        }
+                    }
+                }
+            }
+        }
+
+        nearest
+            .into_iter()
+            .map(|(mac, (_, location))| (mac, location))
+            .collect()
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, time::Duration};
+
+use eui48::MacAddress;
+use snmp::{ObjIdBuf, SyncSession, Value};
+
+use crate::error::Error;
+
+/// BRIDGE-MIB dot1dBasePortIfIndex, the interface behind each bridge port.
+const BASE_PORT_IF_INDEX: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 1, 4, 1, 2];
+/// IF-MIB ifName.
+const IF_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 1];
+/// BRIDGE-MIB dot1dTpFdbPort, the bridge port each address was seen on.
+const FDB_PORT: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 4, 3, 1, 2];
+/// BRIDGE-MIB dot1dTpFdbStatus.
+const FDB_STATUS: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 4, 3, 1, 3];
+/// The status of entries for the bridge's own addresses.
+const STATUS_SELF: i64 = 4;
+
+fn snmp_error(error: snmp::SnmpError) -> Error {
+    Error::SnmpError(format!("{:?}", error))
+}
+
+enum Walked {
+    Integer(i64),
+    Bytes(Vec<u8>),
+}
+
+/// Walks a table, returning each entry's index beneath the table's OID.
+fn walk(session: &mut SyncSession, table: &[u32]) -> Result<Vec<(Vec<u32>, Walked)>, Error> {
+    let mut entries = Vec::new();
+    let mut oid = table.to_vec();
+
+    loop {
+        let response = session.getnext(&oid).map_err(snmp_error)?;
+        let mut varbinds = response.varbinds;
+        let (name, value) = match varbinds.next() {
+            Some(varbind) => varbind,
+            None => break,
+        };
+
+        let mut buf: ObjIdBuf = [0; 128];
+        let name = name.read_name(&mut buf).map_err(snmp_error)?;
+        if !name.starts_with(table) || name.len() == table.len() {
+            break;
+        }
+
+        let value = match value {
+            Value::Integer(value) => Walked::Integer(value),
+            Value::OctetString(bytes) => Walked::Bytes(bytes.to_vec()),
+            Value::EndOfMibView => break,
+            _ => {
+                oid = name.to_vec();
+                continue;
+            }
+        };
+        entries.push((name[table.len()..].to_vec(), value));
+        oid = name.to_vec();
+    }
+
+    Ok(entries)
+}
+
+fn index_mac(index: &[u32]) -> Option<MacAddress> {
+    let bytes: Vec<u8> = index
+        .iter()
+        .map(|b| u8::try_from(*b).ok())
+        .collect::<Option<_>>()?;
+    let bytes: [u8; 6] = bytes.try_into().ok()?;
+    Some(MacAddress::new(bytes))
+}
+
+/// Reads a switch's forwarding table over SNMP v2c, listing the hardware on
+/// each port along with whether it is the switch's own. Ports are named by
+/// their interface name where the switch reports one, otherwise by their
+/// bridge port number.
+pub fn forwarding_table(
+    host: &str,
+    community: &str,
+    version: &str,
+) -> Result<Vec<(String, MacAddress, bool)>, Error> {
+    if version != "2c" {
+        return Err(Error::SnmpError(format!(
+            "unsupported SNMP version {}",
+            version
+        )));
+    }
+
+    let address = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:161", host)
+    };
+    let mut session = SyncSession::new(
+        address,
+        community.as_bytes(),
+        Some(Duration::from_secs(5)),
+        0,
+    )
+    .map_err(Error::IoError)?;
+
+    let mut if_names: HashMap<i64, String> = HashMap::new();
+    for (index, value) in walk(&mut session, IF_NAME)? {
+        if let (Some(index), Walked::Bytes(name)) = (index.first(), value) {
+            if_names.insert(*index as i64, String::from_utf8_lossy(&name).into_owned());
+        }
+    }
+
+    let mut port_names: HashMap<i64, String> = HashMap::new();
+    for (index, value) in walk(&mut session, BASE_PORT_IF_INDEX)? {
+        if let (Some(port), Walked::Integer(if_index)) = (index.first(), value) {
+            if let Some(name) = if_names.get(&if_index) {
+                port_names.insert(*port as i64, name.clone());
+            }
+        }
+    }
+
+    let mut own: Vec<MacAddress> = Vec::new();
+    for (index, value) in walk(&mut session, FDB_STATUS)? {
+        if let (Some(mac), Walked::Integer(STATUS_SELF)) = (index_mac(&index), value) {
+            own.push(mac);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (index, value) in walk(&mut session, FDB_PORT)? {
+        let (mac, port) = match (index_mac(&index), value) {
+            (Some(mac), Walked::Integer(port)) => (mac, port),
+            _ => continue,
+        };
+
+        let port = port_names
+            .get(&port)
+            .cloned()
+            .unwrap_or_else(|| port.to_string());
+        entries.push((port, mac, own.contains(&mac)));
+    }
+
+    Ok(entries)
+}
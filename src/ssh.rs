@@ -0,0 +1,109 @@
+use std::{io::Read, net::TcpStream, path::Path};
+
+use ssh2::Session;
+
+use crate::error::Error;
+
+fn ssh_error(error: ssh2::Error) -> Error {
+    Error::SshError(error.to_string())
+}
+
+/// Something that can run a command on a remote host, returning what it wrote
+/// to stdout and its exit status.
+trait Transport {
+    fn exec(&mut self, command: &str) -> Result<(String, i32), Error>;
+}
+
+impl Transport for Session {
+    fn exec(&mut self, command: &str) -> Result<(String, i32), Error> {
+        let mut channel = self.channel_session().map_err(ssh_error)?;
+        channel.exec(command).map_err(ssh_error)?;
+
+        let mut data = String::new();
+        channel.read_to_string(&mut data).map_err(Error::IoError)?;
+        channel.wait_close().map_err(ssh_error)?;
+
+        let status = channel.exit_status().map_err(ssh_error)?;
+        Ok((data, status))
+    }
+}
+
+fn output<T: Transport>(transport: &mut T, host: &str, command: &str) -> Result<String, Error> {
+    let (data, status) = transport.exec(command)?;
+    if status != 0 {
+        return Err(Error::SshError(format!(
+            "`{}` on {} exited with status {}",
+            command, host, status
+        )));
+    }
+
+    Ok(data)
+}
+
+/// Runs a command on a remote host, authenticating with a private key, and
+/// returns whatever it wrote to stdout.
+pub fn run(host: &str, user: &str, key_path: &Path, command: &str) -> Result<String, Error> {
+    let address = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:22", host)
+    };
+
+    let tcp = TcpStream::connect(address).map_err(Error::IoError)?;
+    let mut session = Session::new().map_err(ssh_error)?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(ssh_error)?;
+    session
+        .userauth_pubkey_file(user, None, key_path, None)
+        .map_err(ssh_error)?;
+
+    output(&mut session, host, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays canned output for the commands it is asked to run.
+    struct Canned {
+        output: &'static str,
+        status: i32,
+        commands: Vec<String>,
+    }
+
+    impl Transport for Canned {
+        fn exec(&mut self, command: &str) -> Result<(String, i32), Error> {
+            self.commands.push(command.to_owned());
+            Ok((self.output.to_owned(), self.status))
+        }
+    }
+
+    #[test]
+    fn output_is_returned_on_success() {
+        let mut transport = Canned {
+            output: "00:11:22:33:44:02 dev lan1\n",
+            status: 0,
+            commands: Vec::new(),
+        };
+
+        let data = output(&mut transport, "sw", "bridge fdb show").unwrap();
+        assert_eq!(data, "00:11:22:33:44:02 dev lan1\n");
+        assert_eq!(transport.commands, vec!["bridge fdb show"]);
+    }
+
+    #[test]
+    fn failing_commands_are_errors() {
+        let mut transport = Canned {
+            output: "",
+            status: 127,
+            commands: Vec::new(),
+        };
+
+        let error = output(&mut transport, "sw", "bridge fdb show").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            Error::SshError("`bridge fdb show` on sw exited with status 127".to_owned())
+                .to_string()
+        );
+    }
+}
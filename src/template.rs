@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+/// Expands device entries with a `count` into that many devices, replacing
+/// `{n}` in their strings with the device's index (counting from 1). Within
+/// MAC addresses the index is written as a two digit hex value.
+pub fn expand(config: &mut Value) {
+    let devices = match config.get_mut("devices").and_then(|d| d.as_array_mut()) {
+        Some(devices) => devices,
+        None => return,
+    };
+
+    let mut expanded = Vec::new();
+    for device in devices.drain(..) {
+        let count = match device.get("count").and_then(|c| c.as_u64()) {
+            Some(count) => count,
+            None => {
+                expanded.push(device);
+                continue;
+            }
+        };
+
+        for n in 1..=count {
+            let mut instance = device.clone();
+            if let Some(object) = instance.as_object_mut() {
+                object.remove("count");
+            }
+            substitute(&mut instance, n, false);
+            expanded.push(instance);
+        }
+    }
+
+    *devices = expanded;
+}
+
+fn substitute(value: &mut Value, n: u64, is_mac: bool) {
+    match value {
+        Value::String(string) => {
+            let index = if is_mac {
+                format!("{:02x}", n)
+            } else {
+                n.to_string()
+            };
+            *string = string.replace("{n}", &index);
+        }
+        Value::Array(values) => {
+            for value in values.iter_mut() {
+                substitute(value, n, is_mac);
+            }
+        }
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                substitute(value, n, is_mac || key == "mac");
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::expand;
+
+    #[test]
+    fn counted_devices_are_expanded() {
+        let mut config = json!({
+            "devices": [
+                {"id": "router", "mac": ["00:11:22:33:44:f0"]},
+                {
+                    "id": "ap{n}",
+                    "name": "Access point {n}",
+                    "count": 3,
+                    "mac": ["00:11:22:33:44:{n}"],
+                    "ports": [{"id": "wlan{n}"}]
+                }
+            ]
+        });
+        expand(&mut config);
+
+        let devices = config["devices"].as_array().unwrap();
+        assert_eq!(devices.len(), 4);
+        assert_eq!(
+            devices[0],
+            json!({"id": "router", "mac": ["00:11:22:33:44:f0"]})
+        );
+        for (n, device) in devices[1..].iter().enumerate() {
+            let n = n + 1;
+            assert_eq!(
+                *device,
+                json!({
+                    "id": format!("ap{}", n),
+                    "name": format!("Access point {}", n),
+                    "mac": [format!("00:11:22:33:44:{:02x}", n)],
+                    "ports": [{"id": format!("wlan{}", n)}]
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Link;
+
+/// The links that are expected to exist in the network.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ExpectedTopology {
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Discrepancy {
+    /// An expected link that wasn't discovered.
+    Missing(Link),
+    /// A discovered link that wasn't expected.
+    Unexpected(Link),
+    /// The expected devices are linked but through different ports.
+    Misplaced { expected: Link, actual: Link },
+}
+
+fn end(device: &str, port: &Option<String>) -> String {
+    match port {
+        Some(port) => format!("{}:{}", device, port),
+        None => device.to_owned(),
+    }
+}
+
+fn describe(link: &Link) -> String {
+    format!(
+        "{} - {}",
+        end(&link.left, &link.left_port),
+        end(&link.right, &link.right_port)
+    )
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::Missing(link) => write!(f, "missing link {}", describe(link)),
+            Discrepancy::Unexpected(link) => write!(f, "unexpected link {}", describe(link)),
+            Discrepancy::Misplaced { expected, actual } => write!(
+                f,
+                "misplaced link {}, found {}",
+                describe(expected),
+                describe(actual)
+            ),
+        }
+    }
+}
+
+fn ports_match(expected: &Option<String>, actual: &Option<String>) -> bool {
+    match (expected, actual) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => true,
+    }
+}
+
+pub fn verify(expected: &ExpectedTopology, discovered: &[Link]) -> Vec<Discrepancy> {
+    let mut discrepancies = Vec::new();
+    let mut matched = vec![false; discovered.len()];
+
+    for link in expected.links.iter() {
+        let link = link.normalized();
+
+        let found = discovered
+            .iter()
+            .position(|d| d.left == link.left && d.right == link.right);
+
+        match found {
+            None => discrepancies.push(Discrepancy::Missing(link)),
+            Some(index) => {
+                matched[index] = true;
+                let actual = &discovered[index];
+
+                if !ports_match(&link.left_port, &actual.left_port)
+                    || !ports_match(&link.right_port, &actual.right_port)
+                {
+                    discrepancies.push(Discrepancy::Misplaced {
+                        expected: link,
+                        actual: actual.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    for (link, matched) in discovered.iter().zip(matched) {
+        if !matched {
+            discrepancies.push(Discrepancy::Unexpected(link.clone()));
+        }
+    }
+
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(left: &str, left_port: Option<&str>, right: &str, right_port: Option<&str>) -> Link {
+        Link {
+            left: left.to_owned(),
+            left_port: left_port.map(|p| p.to_owned()),
+            right: right.to_owned(),
+            right_port: right_port.map(|p| p.to_owned()),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn moved_cables_are_misplaced() {
+        // Written the other way around to the discovered link.
+        let expected = ExpectedTopology {
+            links: vec![link("sw", Some("lan1"), "r", None)],
+        };
+        let discovered = vec![link("r", None, "sw", Some("lan2"))];
+
+        assert_eq!(
+            verify(&expected, &discovered),
+            vec![Discrepancy::Misplaced {
+                expected: link("r", None, "sw", Some("lan1")),
+                actual: discovered[0].clone(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unexpected_links_are_reported() {
+        let expected = ExpectedTopology {
+            links: vec![link("r", None, "sw", None)],
+        };
+        let discovered = vec![
+            link("r", None, "sw", Some("lan1")),
+            link("ap", None, "sw", Some("lan2")),
+        ];
+
+        assert_eq!(
+            verify(&expected, &discovered),
+            vec![Discrepancy::Unexpected(discovered[1].clone())]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+fn config(name: &str, fdb: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("netmap-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("sw.fdb"), fdb).unwrap();
+
+    let config = root.join("netmap.json");
+    fs::write(
+        &config,
+        r#"{
+            "devices": [
+                {"id": "sw", "mac": ["00:11:22:33:44:01"], "ports": [{"id": "lan1"}],
+                 "pollers": [{"type": "file", "file": "sw.fdb", "format": "fdb"}]},
+                {"id": "r", "mac": ["00:11:22:33:44:02"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    config
+}
+
+fn netmap(args: &[&str], config: &Path) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_netmap"))
+        .arg("poll")
+        .args(args)
+        .arg(config)
+        .output()
+        .unwrap()
+        .status
+        .code()
+}
+
+#[test]
+fn empty_maps_fail_when_asked() {
+    let empty = config("fail_if_empty", "");
+    assert_eq!(netmap(&["--fail-if-empty"], &empty), Some(2));
+    assert_eq!(netmap(&[], &empty), Some(0));
+
+    let linked = config("fail_if_empty_linked", "00:11:22:33:44:02 dev lan1\n");
+    assert_eq!(netmap(&["--fail-if-empty"], &linked), Some(0));
+}